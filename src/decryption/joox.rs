@@ -22,8 +22,12 @@ mod detail {
 
         aes: Aes128,
         block_count: usize,
+
+        declared_size: u64,
+        output_size: u64,
     }
 
+    const JOOX_MAGIC_PREFIX: &[u8; 3] = b"E!0";
     const JOOX_V04_MAGIC: u32 = u32::from_be_bytes(*b"E!04");
     const JOOX_V04_HEADER_SIZE: usize = 12; // 'E!04' + u64_be(file size)
 
@@ -56,9 +60,43 @@ mod detail {
                 state: State::ReadFileMagic,
                 aes: Aes128::new_from_slice(aes_key).ok().unwrap(),
                 block_count: 0,
+                declared_size: 0,
+                output_size: 0,
+            }
+        }
+
+        fn check_magic(&self) -> Result<(), DecryptError> {
+            let magic = &self.data.buf_in[..4];
+            if magic.read_be::<u32>(0) == JOOX_V04_MAGIC {
+                return Ok(());
+            }
+
+            match magic.strip_prefix(JOOX_MAGIC_PREFIX) {
+                Some([version]) if version.is_ascii_digit() => Err(DecryptError::new(
+                    DecryptErrorCode::UnsupportedVersion,
+                    &format!("unsupported version {}", *version as char),
+                )),
+                _ => Err(DecryptError::new(
+                    DecryptErrorCode::UnknownMagicHeader,
+                    "unknown magic header",
+                )),
             }
         }
 
+        fn check_output_size(&mut self) -> Result<(), DecryptError> {
+            if self.output_size != self.declared_size {
+                let error = DecryptError::new(
+                    DecryptErrorCode::OutputSizeMismatch,
+                    &format!(
+                        "output size mismatch: declared {}, got {}",
+                        self.declared_size, self.output_size
+                    ),
+                );
+                return self.data.recover_or_fail(self.data.offset, 0, error);
+            }
+            Ok(())
+        }
+
         #[inline(always)]
         fn decrypt_aes_block(&mut self) {
             let mut data = [0u8; JOOX_V04_AES_BLOCK_SIZE];
@@ -68,6 +106,7 @@ mod detail {
             let mut block = GenericArray::from(data);
             self.aes.decrypt_block(&mut block);
             self.data.buf_out.extend_from_slice(block.as_slice());
+            self.output_size += JOOX_V04_AES_BLOCK_SIZE as u64;
         }
 
         #[inline]
@@ -75,20 +114,27 @@ mod detail {
             let mut data = [0u8; JOOX_V04_AES_BLOCK_SIZE];
             data.copy_from_slice(&self.data.buf_in[0..JOOX_V04_AES_BLOCK_SIZE]);
             self.data.offset += JOOX_V04_AES_BLOCK_SIZE;
-            let data_input = data;
             self.data.seek_input(JOOX_V04_AES_BLOCK_SIZE);
             let mut block = GenericArray::from(data);
 
-            self.aes
-                .decrypt_padded::<Pkcs7>(&mut block)
-                .map_err(|_| {
-                    let _a = data_input;
-                    let _b = self.data.offset;
-                    DecryptError::new(DecryptErrorCode::AESParamError, "un-pad error")
-                })
-                .map(|result| {
-                    self.data.buf_out.extend_from_slice(result);
-                })
+            if let Ok(result) = self.aes.decrypt_padded::<Pkcs7>(&mut block) {
+                self.data.buf_out.extend_from_slice(result);
+                self.output_size += result.len() as u64;
+                return Ok(());
+            }
+
+            self.data.recover_or_fail(
+                self.data.offset - JOOX_V04_AES_BLOCK_SIZE,
+                JOOX_V04_AES_BLOCK_SIZE,
+                DecryptError::new(DecryptErrorCode::AESParamError, "un-pad error"),
+            )?;
+
+            // Best guess: keep the whole block, without un-padding.
+            let mut block = GenericArray::from(data);
+            self.aes.decrypt_block(&mut block);
+            self.data.buf_out.extend_from_slice(block.as_slice());
+            self.output_size += JOOX_V04_AES_BLOCK_SIZE as u64;
+            Ok(())
         }
     }
 
@@ -96,7 +142,40 @@ mod detail {
         crate::impl_decryptor_inner_helper! {}
 
         fn end(&mut self) -> Result<(), DecryptError> {
-            self.decrypt_aes_padding_block()
+            // Input offset of `buf_in`, once the header was read.
+            let body_offset = match self.state {
+                State::ReadFileMagic | State::SeekToBody(_) => None,
+                State::DecryptOtherBlock | State::DecryptPaddingBlock => Some(self.data.offset),
+            };
+
+            if body_offset.is_some() && self.data.buf_in.len() >= JOOX_V04_AES_BLOCK_SIZE {
+                self.decrypt_aes_padding_block()?;
+
+                // Misaligned tail, or junk after the last block.
+                let len = self.data.buf_in.len();
+                if len != 0 {
+                    self.data.recover_or_fail(
+                        self.data.offset,
+                        len,
+                        DecryptError::new(
+                            DecryptErrorCode::InvalidBlockSize,
+                            "trailing data after the last block",
+                        ),
+                    )?;
+                    self.data.seek_input(len);
+                }
+            } else {
+                // Truncated inside the header, or before the last padding block.
+                let len = self.data.buf_in.len();
+                self.data.recover_or_fail(
+                    body_offset.unwrap_or_default(),
+                    len,
+                    DecryptError::new(DecryptErrorCode::InvalidBlockSize, "unexpected eof"),
+                )?;
+                self.data.seek_input(len);
+            }
+
+            self.check_output_size()
         }
 
         fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
@@ -106,18 +185,13 @@ mod detail {
                 match self.state {
                     State::ReadFileMagic => {
                         if self.data.read_until_offset(&mut p, 4) {
-                            let magic = self.data.buf_in.read_be::<u32>(0);
-                            if magic != JOOX_V04_MAGIC {
-                                return Err(DecryptError::new(
-                                    DecryptErrorCode::UnknownMagicHeader,
-                                    "unsupported joox version",
-                                ));
-                            }
+                            self.check_magic()?;
                             self.state = State::SeekToBody(JOOX_V04_HEADER_SIZE);
                         }
                     }
                     State::SeekToBody(n) => {
                         if self.data.read_until_offset(&mut p, n) {
+                            self.declared_size = self.data.buf_in.read_be::<u64>(4);
                             self.data.seek_input(n);
                             self.state = State::DecryptOtherBlock;
                         }
@@ -154,10 +228,33 @@ pub use detail::new_joox;
 
 #[cfg(test)]
 pub mod test {
-    use crate::utils::test_util::test::{
-        decrypt_test_content, generate_test_data, TEST_SIZE_1MB, TEST_SIZE_4MB,
+    use aes::Aes128;
+    use cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit};
+    use ring::pbkdf2;
+    use std::num::NonZeroU32;
+
+    use crate::{
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::test_util::test::{
+            decrypt_test_content, generate_test_data, TEST_SIZE_1MB, TEST_SIZE_4MB,
+        },
     };
 
+    fn new_test_aes(uuid: &str) -> Aes128 {
+        let mut key = [0u8; 20];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA1,
+            NonZeroU32::new(1000).unwrap(),
+            &[
+                0xa4, 0x0b, 0xc8, 0x34, 0xd6, 0x95, 0xf3, 0x13, //
+                0x23, 0x23, 0x43, 0x23, 0x54, 0x63, 0x83, 0xf3, //
+            ],
+            uuid.as_bytes(),
+            &mut key,
+        );
+        Aes128::new_from_slice(&key[..16]).unwrap()
+    }
+
     #[test]
     fn test_joox() {
         let mut test_data = generate_test_data(TEST_SIZE_4MB + 12, "joox test data");
@@ -173,6 +270,7 @@ pub mod test {
         }
         let n = test_data.len();
         test_data[n - 16..].copy_from_slice(padding_data);
+        test_data[4..12].copy_from_slice(&(TEST_SIZE_4MB as u64 - 64).to_be_bytes());
 
         let mut decryptor =
             super::new_joox(unsafe { std::str::from_utf8_unchecked(uuid.as_slice()) }.to_string());
@@ -182,4 +280,117 @@ pub mod test {
             "68feeeb99b826608032811a14dcb8d3f712a5a984a884c1fe487b50220da862c"
         );
     }
+
+    #[test]
+    fn test_joox_header_checks() {
+        let uuid = String::from("joox-uuid");
+
+        let mut decryptor = super::new_joox(uuid.clone());
+        let err = decryptor.write(b"E!05").unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::UnsupportedVersion));
+        assert!(format!("{:?}", err).contains("unsupported version 5"));
+
+        let mut decryptor = super::new_joox(uuid.clone());
+        let err = decryptor.write(b"ID3\x04").unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::UnknownMagicHeader));
+
+        // Declared size of 1 byte, but the body is a single block of padding.
+        let mut padding_block = GenericArray::from([0x10u8; 16]);
+        new_test_aes(&uuid).encrypt_block(&mut padding_block);
+
+        let mut test_data = Vec::from(&b"E!04"[..]);
+        test_data.extend_from_slice(&1u64.to_be_bytes());
+        test_data.extend_from_slice(&padding_block);
+        let mut decryptor = super::new_joox(uuid);
+        decryptor.write(&test_data).unwrap();
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::OutputSizeMismatch));
+    }
+
+    #[test]
+    fn test_joox_recovery() {
+        // A single, corrupted padding block.
+        let mut test_data = Vec::from(&b"E!04"[..]);
+        test_data.extend_from_slice(&16u64.to_be_bytes());
+        test_data.extend_from_slice(&generate_test_data(16, "joox bad padding"));
+
+        let mut decryptor = super::new_joox(String::from("joox-uuid"));
+        decryptor.write(&test_data).unwrap();
+        assert!(decryptor.end().is_err());
+
+        let mut decryptor = super::new_joox(String::from("joox-uuid"));
+        decryptor.set_recovery_mode(true);
+        decryptor.write(&test_data).unwrap();
+        decryptor.end().unwrap();
+        assert_eq!(decryptor.read_all_output().len(), 16);
+
+        let damaged = decryptor.get_damaged_ranges();
+        assert_eq!(damaged.len(), 1);
+        assert_eq!((damaged[0].offset, damaged[0].len), (12, 16));
+        assert!(matches!(
+            damaged[0].error.code(),
+            DecryptErrorCode::AESParamError
+        ));
+    }
+
+    #[test]
+    fn test_joox_truncated() {
+        // (input, damaged range of the leftover bytes)
+        let cases: [(&[u8], (usize, usize)); 3] = [
+            (b"E!04\x00\x00", (0, 6)),
+            (b"E!04\x00\x00\x00\x00\x00\x00\x00\x00", (12, 0)),
+            (b"E!04\x00\x00\x00\x00\x00\x00\x00\x00abcde", (12, 5)),
+        ];
+
+        for (test_data, range) in cases {
+            let mut decryptor = super::new_joox(String::from("joox-uuid"));
+            decryptor.write(test_data).unwrap();
+            let err = decryptor.end().unwrap_err();
+            assert!(matches!(err.code(), DecryptErrorCode::InvalidBlockSize));
+
+            let mut decryptor = super::new_joox(String::from("joox-uuid"));
+            decryptor.set_recovery_mode(true);
+            decryptor.write(test_data).unwrap();
+            decryptor.end().unwrap();
+            assert!(decryptor.read_all_output().is_empty());
+
+            let damaged = decryptor.get_damaged_ranges();
+            assert_eq!((damaged[0].offset, damaged[0].len), range);
+            assert!(matches!(
+                damaged[0].error.code(),
+                DecryptErrorCode::InvalidBlockSize
+            ));
+        }
+    }
+
+    #[test]
+    fn test_joox_trailing_data() {
+        // "hello" in a single padded block, followed by a misaligned tail.
+        let mut block = GenericArray::from(*b"hello\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b");
+        new_test_aes("joox-uuid").encrypt_block(&mut block);
+
+        let mut test_data = Vec::from(&b"E!04"[..]);
+        test_data.extend_from_slice(&5u64.to_be_bytes());
+        test_data.extend_from_slice(&block);
+        test_data.extend_from_slice(b"abcde");
+
+        let mut decryptor = super::new_joox(String::from("joox-uuid"));
+        decryptor.write(&test_data).unwrap();
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidBlockSize));
+
+        let mut decryptor = super::new_joox(String::from("joox-uuid"));
+        decryptor.set_recovery_mode(true);
+        decryptor.write(&test_data).unwrap();
+        decryptor.end().unwrap();
+        assert_eq!(decryptor.read_all_output(), b"hello");
+
+        let damaged = decryptor.get_damaged_ranges();
+        assert_eq!(damaged.len(), 1);
+        assert_eq!((damaged[0].offset, damaged[0].len), (28, 5));
+        assert!(matches!(
+            damaged[0].error.code(),
+            DecryptErrorCode::InvalidBlockSize
+        ));
+    }
 }
@@ -1,35 +1,198 @@
+use std::collections::HashMap;
+
 pub const KUGOU_INTERNAL_TABLE_SIZE: usize = 17 * 16;
 pub const KUGOU_VPR_KEY_SIZE: usize = 17;
 pub const KUGOU_FILE_KEY_SIZE: usize = 17;
+pub const KUGOU_DEFAULT_KEY_SLOT: u32 = 1;
 
 pub type KugouInternalTable = [u8; KUGOU_INTERNAL_TABLE_SIZE];
 pub type KugouVPRKey = [u8; KUGOU_VPR_KEY_SIZE];
 pub type KugouFileKey = [u8; KUGOU_FILE_KEY_SIZE];
 
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KugouFileType {
+    /// `.kgm` and `.kgg` files.
+    KGM,
+    /// `.vpr` files.
+    VPR,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KugouHeader {
+    pub file_type: KugouFileType,
+    /// Offset of the encrypted audio data.
+    pub header_size: usize,
+    pub crypto_version: u32,
+    pub key_slot: u32,
+    pub file_key: [u8; 16],
+    /// Only present in crypto version 5 (`.kgg`) files.
+    pub audio_hash: Option<String>,
+}
+
+/// Tables used by the KGM/VPR cipher for a single key slot.
+#[derive(Debug, Clone)]
+pub struct KugouKeyTables {
+    pub t1: KugouInternalTable,
+    pub t2: KugouInternalTable,
+    pub v2: KugouInternalTable,
+}
+
+impl KugouKeyTables {
+    pub fn new(t1: &KugouInternalTable, t2: &KugouInternalTable, v2: &KugouInternalTable) -> Self {
+        Self {
+            t1: *t1,
+            t2: *t2,
+            v2: *v2,
+        }
+    }
+}
+
+/// Key tables indexed by the key slot declared in the file header.
+pub type KugouSlotTables = HashMap<u32, KugouKeyTables>;
+
 mod detail {
     use std::cmp::Ordering;
 
     use crate::{
-        decryptor::{BaseDecryptorData, DecryptError, DecryptErrorCode, Decryptor},
+        decryption::qmc::{new_qmc_v2_from_key, QMCv2Cipher},
+        decryptor::{BaseDecryptorData, BodyDecryptor, DecryptError, DecryptErrorCode, Decryptor},
         impl_decryptor_inner_helper,
+        tencent::qmc_footer::parse_ekey,
         utils::array_ext::{ArrayExtension, ByteSliceExt},
     };
 
-    use super::{KugouFileKey, KugouInternalTable, KugouVPRKey, KUGOU_FILE_KEY_SIZE};
+    use super::{
+        KugouFileKey, KugouFileType, KugouHeader, KugouInternalTable, KugouKeyTables,
+        KugouSlotTables, KugouVPRKey, KUGOU_DEFAULT_KEY_SLOT, KUGOU_FILE_KEY_SIZE,
+        KUGOU_INTERNAL_TABLE_SIZE,
+    };
 
     const KUGOU_MAGIC_HEADER_SIZE: usize = 16;
     const MINIMAL_HEADER_SIZE: usize = 0x2c;
 
     type KugouMagicHeader = [u8; KUGOU_MAGIC_HEADER_SIZE];
 
+    const KGM_MAGIC_HEADER: &KugouMagicHeader = &[
+        0x7c, 0xd5, 0x32, 0xeb, 0x86, 0x02, 0x7f, 0x4b, //
+        0xa8, 0xaf, 0xa6, 0x8e, 0x0f, 0xff, 0x99, 0x14, //
+    ];
+
+    const VPR_MAGIC_HEADER: &KugouMagicHeader = &[
+        0x05, 0x28, 0xbc, 0x96, 0xe9, 0xe4, 0x5a, 0x43, //
+        0x91, 0xaa, 0xbd, 0xd0, 0x7a, 0xf5, 0x36, 0x31, //
+    ];
+
+    // Kugou header:
+    //   0x00: [magic; 16]
+    //   0x10: u32_le header_size
+    //   0x14: u32_le crypto_version
+    //   0x18: u32_le key_slot
+    //   0x1c: [file_key; 16]
+    // Crypto version 5 only:
+    //   0x44: u32_le audio_hash_len
+    //   0x48: [ansi audio_hash; audio_hash_len]
+    const HEADER_SIZE_OFFSET: usize = 0x10;
+    const CRYPTO_VERSION_OFFSET: usize = 0x14;
+    const KEY_SLOT_OFFSET: usize = 0x18;
+    const FILE_KEY_OFFSET: usize = 0x1c;
+    const AUDIO_HASH_LEN_OFFSET: usize = 0x44;
+    const AUDIO_HASH_OFFSET: usize = 0x48;
+
+    const KGM_CRYPTO_VERSION: u32 = 3;
+    const KGG_CRYPTO_VERSION: u32 = 5;
+
+    fn parse_file_type(buf: &[u8]) -> Result<KugouFileType, DecryptError> {
+        let magic = &buf[..KUGOU_MAGIC_HEADER_SIZE];
+        if magic.cmp(KGM_MAGIC_HEADER) == Ordering::Equal {
+            Ok(KugouFileType::KGM)
+        } else if magic.cmp(VPR_MAGIC_HEADER) == Ordering::Equal {
+            Ok(KugouFileType::VPR)
+        } else {
+            Err(DecryptError::new(
+                DecryptErrorCode::UnknownMagicHeader,
+                "unknown magic header",
+            ))
+        }
+    }
+
+    /// File type, and the number of bytes required to parse the header
+    ///   based on what has been read so far; `buf` must hold `MINIMAL_HEADER_SIZE` bytes.
+    fn get_header_parse_size(buf: &[u8]) -> Result<(KugouFileType, usize), DecryptError> {
+        let file_type = parse_file_type(buf)?;
+        let header_size = buf.read_le::<u32>(HEADER_SIZE_OFFSET) as usize;
+        let parse_size = match buf.read_le::<u32>(CRYPTO_VERSION_OFFSET) {
+            KGG_CRYPTO_VERSION if buf.len() < AUDIO_HASH_OFFSET => AUDIO_HASH_OFFSET,
+            KGG_CRYPTO_VERSION => {
+                AUDIO_HASH_OFFSET + buf.read_le::<u32>(AUDIO_HASH_LEN_OFFSET) as usize
+            }
+            _ => MINIMAL_HEADER_SIZE,
+        };
+
+        if header_size < parse_size {
+            return Err(DecryptError::new(
+                DecryptErrorCode::InvalidBlockSize,
+                "header_size too small",
+            ));
+        }
+
+        Ok((file_type, parse_size))
+    }
+
+    pub fn parse_header(buf: &[u8]) -> Result<KugouHeader, DecryptError> {
+        let truncated =
+            || DecryptError::new(DecryptErrorCode::InvalidBlockSize, "header truncated");
+        if buf.len() < MINIMAL_HEADER_SIZE {
+            return Err(truncated());
+        }
+
+        let (file_type, parse_size) = get_header_parse_size(buf)?;
+        if buf.len() < parse_size {
+            return Err(truncated());
+        }
+
+        Ok(read_header(buf, file_type, parse_size))
+    }
+
+    /// `buf` must hold `parse_size` bytes, see `get_header_parse_size`.
+    fn read_header(buf: &[u8], file_type: KugouFileType, parse_size: usize) -> KugouHeader {
+        let crypto_version = buf.read_le::<u32>(CRYPTO_VERSION_OFFSET);
+        let audio_hash = if crypto_version == KGG_CRYPTO_VERSION {
+            let audio_hash = &buf[AUDIO_HASH_OFFSET..parse_size];
+            Some(String::from_utf8_lossy(audio_hash).into_owned())
+        } else {
+            None
+        };
+
+        let mut file_key = [0u8; 16];
+        file_key.copy_from_slice(&buf[FILE_KEY_OFFSET..FILE_KEY_OFFSET + 16]);
+
+        KugouHeader {
+            file_type,
+            header_size: buf.read_le::<u32>(HEADER_SIZE_OFFSET) as usize,
+            crypto_version,
+            key_slot: buf.read_le::<u32>(KEY_SLOT_OFFSET),
+            file_key,
+            audio_hash,
+        }
+    }
+
+    #[inline]
+    fn unsupported_version_error(crypto_version: u32) -> DecryptError {
+        DecryptError::new(
+            DecryptErrorCode::UnsupportedVersion,
+            &format!("unsupported kugou crypto version {}", crypto_version),
+        )
+    }
+
     enum State {
-        ReadFileMagic,
+        ReadFileHeader(usize),
         SeekToBody(usize),
         Decrypt,
     }
 
     trait KugouAlgo {
-        fn get_magic_header(&self) -> &'static KugouMagicHeader;
+        fn get_file_type(&self) -> KugouFileType;
         fn get_vpr_key_at_offset(&self, offset: usize) -> u8;
     }
 
@@ -44,11 +207,8 @@ mod detail {
 
     impl KugouAlgo for KugouKGM {
         #[inline]
-        fn get_magic_header(&self) -> &'static KugouMagicHeader {
-            &[
-                0x7c, 0xd5, 0x32, 0xeb, 0x86, 0x02, 0x7f, 0x4b, //
-                0xa8, 0xaf, 0xa6, 0x8e, 0x0f, 0xff, 0x99, 0x14, //
-            ]
+        fn get_file_type(&self) -> KugouFileType {
+            KugouFileType::KGM
         }
 
         #[inline]
@@ -70,11 +230,8 @@ mod detail {
 
     impl KugouAlgo for KugouVPR {
         #[inline]
-        fn get_magic_header(&self) -> &'static KugouMagicHeader {
-            &[
-                0x05, 0x28, 0xbc, 0x96, 0xe9, 0xe4, 0x5a, 0x43, //
-                0x91, 0xaa, 0xbd, 0xd0, 0x7a, 0xf5, 0x36, 0x31, //
-            ]
+        fn get_file_type(&self) -> KugouFileType {
+            KugouFileType::VPR
         }
 
         #[inline]
@@ -87,6 +244,7 @@ mod detail {
         data: BaseDecryptorData,
         state: State,
 
+        slot_tables: KugouSlotTables,
         t1: KugouInternalTable,
         t2: KugouInternalTable,
         v2: KugouInternalTable,
@@ -95,24 +253,46 @@ mod detail {
     }
 
     impl<T: KugouAlgo> Kugou<T> {
-        pub fn new(
-            name: &str,
-            t1: &KugouInternalTable,
-            t2: &KugouInternalTable,
-            v2: &KugouInternalTable,
-            detail: T,
-        ) -> Kugou<T> {
+        pub fn new(name: &str, slot_tables: KugouSlotTables, detail: T) -> Kugou<T> {
             Kugou {
                 data: BaseDecryptorData::new(name),
-                state: State::ReadFileMagic,
-                t1: *t1,
-                t2: *t2,
-                v2: *v2,
+                state: State::ReadFileHeader(MINIMAL_HEADER_SIZE),
+                slot_tables,
+                t1: [0u8; KUGOU_INTERNAL_TABLE_SIZE],
+                t2: [0u8; KUGOU_INTERNAL_TABLE_SIZE],
+                v2: [0u8; KUGOU_INTERNAL_TABLE_SIZE],
                 file_key: [0u8; KUGOU_FILE_KEY_SIZE],
                 detail,
             }
         }
 
+        fn init_key(&mut self, header: &KugouHeader) -> Result<(), DecryptError> {
+            if header.file_type != self.detail.get_file_type() {
+                return Err(DecryptError::new(
+                    DecryptErrorCode::UnknownMagicHeader,
+                    "unknown magic header",
+                ));
+            }
+
+            if header.crypto_version != KGM_CRYPTO_VERSION {
+                return Err(unsupported_version_error(header.crypto_version));
+            }
+
+            let tables = self.slot_tables.get(&header.key_slot).ok_or_else(|| {
+                DecryptError::new(
+                    DecryptErrorCode::KeyNotFound,
+                    &format!("no key tables for key slot {}", header.key_slot),
+                )
+            })?;
+
+            self.t1 = tables.t1;
+            self.t2 = tables.t2;
+            self.v2 = tables.v2;
+            self.file_key[0..16].copy_from_slice(&header.file_key);
+            self.file_key[16] = 0;
+            Ok(())
+        }
+
         fn get_mask_v2(&self, offset: usize) -> u8 {
             let mut value = 0u8;
             let mut offset = offset;
@@ -162,24 +342,17 @@ mod detail {
 
             while !p.is_empty() {
                 match self.state {
-                    State::ReadFileMagic => {
-                        if self.data.read_until_offset(&mut p, MINIMAL_HEADER_SIZE) {
-                            let expected_header = self.detail.get_magic_header();
-
-                            if self.data.buf_in[..KUGOU_MAGIC_HEADER_SIZE].cmp(&expected_header[..])
-                                != Ordering::Equal
-                            {
-                                return Err(DecryptError::new(
-                                    DecryptErrorCode::UnknownMagicHeader,
-                                    "unknown magic header",
-                                ));
+                    State::ReadFileHeader(n) => {
+                        if self.data.read_until_offset(&mut p, n) {
+                            let (file_type, parse_size) = get_header_parse_size(&self.data.buf_in)?;
+                            if parse_size > n {
+                                self.state = State::ReadFileHeader(parse_size);
+                                continue;
                             }
 
-                            let header_size = self.data.buf_in.read_le::<u32>(0x10) as usize;
-                            self.file_key[0..16].copy_from_slice(&self.data.buf_in[0x1c..0x2c]);
-                            self.file_key[16] = 0;
-
-                            self.state = State::SeekToBody(header_size);
+                            let header = read_header(&self.data.buf_in, file_type, parse_size);
+                            self.init_key(&header)?;
+                            self.state = State::SeekToBody(header.header_size);
                         }
                     }
 
@@ -201,12 +374,129 @@ mod detail {
         }
     }
 
+    // KGG (crypto version 5): the body is QMCv2 encrypted,
+    //   keyed by the ekey of `audio_hash` from the client's key database.
+    struct KugouKGG<F: Fn(&str) -> Option<String>> {
+        data: BaseDecryptorData,
+        state: State,
+        ekey_lookup: F,
+        qmc: Option<BodyDecryptor>,
+    }
+
+    impl<F: Fn(&str) -> Option<String>> KugouKGG<F> {
+        fn new(ekey_lookup: F) -> Self {
+            Self {
+                data: BaseDecryptorData::new("Kugou(KGG)"),
+                state: State::ReadFileHeader(MINIMAL_HEADER_SIZE),
+                ekey_lookup,
+                qmc: None,
+            }
+        }
+
+        fn init_qmc(&mut self, header: &KugouHeader) -> Result<(), DecryptError> {
+            if header.file_type != KugouFileType::KGM {
+                return Err(DecryptError::new(
+                    DecryptErrorCode::UnknownMagicHeader,
+                    "unknown magic header",
+                ));
+            }
+
+            let audio_hash = match (header.crypto_version, &header.audio_hash) {
+                (KGG_CRYPTO_VERSION, Some(audio_hash)) => audio_hash,
+                (crypto_version, _) => return Err(unsupported_version_error(crypto_version)),
+            };
+
+            let ekey = (self.ekey_lookup)(audio_hash).ok_or_else(|| {
+                DecryptError::new(
+                    DecryptErrorCode::KeyNotFound,
+                    &format!("ekey not found for audio hash {}", audio_hash),
+                )
+            })?;
+
+            let key = parse_ekey(&ekey)
+                .filter(|key| !key.is_empty())
+                .ok_or_else(|| DecryptError::new(DecryptErrorCode::InvalidEKey, "invalid ekey"))?;
+
+            let cipher = QMCv2Cipher::from_key_len(key.len());
+            self.qmc = Some(BodyDecryptor::new(
+                new_qmc_v2_from_key(key, 0, cipher),
+                header.header_size,
+                self.data.recovery_mode,
+            ));
+            Ok(())
+        }
+    }
+
+    impl<F: Fn(&str) -> Option<String>> Decryptor for KugouKGG<F> {
+        impl_decryptor_inner_helper! {}
+
+        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            let mut p = data;
+
+            while !p.is_empty() {
+                match self.state {
+                    State::ReadFileHeader(n) => {
+                        if self.data.read_until_offset(&mut p, n) {
+                            let (file_type, parse_size) = get_header_parse_size(&self.data.buf_in)?;
+                            if parse_size > n {
+                                self.state = State::ReadFileHeader(parse_size);
+                                continue;
+                            }
+
+                            let header = read_header(&self.data.buf_in, file_type, parse_size);
+                            self.init_qmc(&header)?;
+                            self.state = State::SeekToBody(header.header_size);
+                        }
+                    }
+
+                    State::SeekToBody(n) => {
+                        if self.data.read_until_offset(&mut p, n) {
+                            self.state = State::Decrypt;
+                            self.data.offset = 0;
+                            self.data.seek_input(n);
+                        }
+                    }
+
+                    State::Decrypt => {
+                        if let Some(qmc) = self.qmc.as_mut() {
+                            qmc.write(&mut self.data, p)?;
+                            self.data.offset += p.len();
+                        }
+                        return Ok(());
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        fn end(&mut self) -> Result<(), DecryptError> {
+            if let Some(qmc) = self.qmc.as_mut() {
+                qmc.end(&mut self.data)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[inline]
+    fn default_slot_tables(
+        t1: &KugouInternalTable,
+        t2: &KugouInternalTable,
+        v2: &KugouInternalTable,
+    ) -> KugouSlotTables {
+        KugouSlotTables::from([(KUGOU_DEFAULT_KEY_SLOT, KugouKeyTables::new(t1, t2, v2))])
+    }
+
     pub fn new_kgm(
         t1: &KugouInternalTable,
         t2: &KugouInternalTable,
         v2: &KugouInternalTable,
     ) -> impl Decryptor {
-        Kugou::new("Kugou(KGM)", t1, t2, v2, KugouKGM::new())
+        new_kgm_with_slots(default_slot_tables(t1, t2, v2))
+    }
+
+    pub fn new_kgm_with_slots(slot_tables: KugouSlotTables) -> impl Decryptor {
+        Kugou::new("Kugou(KGM)", slot_tables, KugouKGM::new())
     }
 
     pub fn new_vpr(
@@ -215,21 +505,41 @@ mod detail {
         v2: &KugouInternalTable,
         vpr_key: &KugouVPRKey,
     ) -> impl Decryptor {
-        Kugou::new("Kugou(VPR)", t1, t2, v2, KugouVPR::new(vpr_key))
+        new_vpr_with_slots(default_slot_tables(t1, t2, v2), vpr_key)
+    }
+
+    pub fn new_vpr_with_slots(
+        slot_tables: KugouSlotTables,
+        vpr_key: &KugouVPRKey,
+    ) -> impl Decryptor {
+        Kugou::new("Kugou(VPR)", slot_tables, KugouVPR::new(vpr_key))
+    }
+
+    /// `ekey_lookup` maps the audio hash from the file header to its ekey,
+    /// as stored in the client's key database.
+    pub fn new_kgg<F: Fn(&str) -> Option<String>>(ekey_lookup: F) -> impl Decryptor {
+        KugouKGG::new(ekey_lookup)
     }
 }
 
+pub use detail::new_kgg;
 pub use detail::new_kgm;
+pub use detail::new_kgm_with_slots;
 pub use detail::new_vpr;
+pub use detail::new_vpr_with_slots;
+pub use detail::parse_header;
 
 #[cfg(test)]
 pub mod test {
     use crate::{
         decryption::kugou::KUGOU_VPR_KEY_SIZE,
-        utils::test_util::test::{decrypt_test_content, generate_test_data, TEST_SIZE_4MB},
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::test_util::test::{
+            decrypt_test_content, generate_test_data, TEST_QMC_V2_RC4_EKEY, TEST_SIZE_4MB,
+        },
     };
 
-    use super::KUGOU_INTERNAL_TABLE_SIZE;
+    use super::{KugouFileType, KugouKeyTables, KugouSlotTables, KUGOU_INTERNAL_TABLE_SIZE};
 
     #[test]
     fn test_kgm() {
@@ -241,9 +551,10 @@ pub mod test {
         t2[..16].fill(0);
 
         let mut test_data = generate_test_data(TEST_SIZE_4MB, "kgm_test1_data");
-        test_data[..20].copy_from_slice(&[
+        test_data[..28].copy_from_slice(&[
             0x7C, 0xD5, 0x32, 0xEB, 0x86, 0x02, 0x7F, 0x4B, 0xA8, 0xAF, //
             0xA6, 0x8E, 0x0F, 0xFF, 0x99, 0x14, 0x00, 0x04, 0x00, 0x00, //
+            0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, //
         ]);
 
         let mut decryptor = super::new_kgm(
@@ -270,9 +581,10 @@ pub mod test {
         t2[..16].fill(0);
 
         let mut test_data = generate_test_data(TEST_SIZE_4MB, "vpr_test1_data");
-        test_data[..20].copy_from_slice(&[
+        test_data[..28].copy_from_slice(&[
             0x05, 0x28, 0xbc, 0x96, 0xe9, 0xe4, 0x5a, 0x43, 0x91, 0xaa, //
             0xbd, 0xd0, 0x7a, 0xf5, 0x36, 0x31, 0x00, 0x04, 0x00, 0x00, //
+            0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, //
         ]);
 
         let mut decryptor = super::new_vpr(
@@ -288,4 +600,92 @@ pub mod test {
             "9f8786693b334d074b0ef5c573672c9cf290fae204b285240c18f93cd7ebaca5"
         );
     }
+
+    #[test]
+    fn test_kgm_header_checks() {
+        let table = [0u8; KUGOU_INTERNAL_TABLE_SIZE];
+        let mut header = vec![0u8; 0x400];
+        header[..28].copy_from_slice(&[
+            0x7C, 0xD5, 0x32, 0xEB, 0x86, 0x02, 0x7F, 0x4B, 0xA8, 0xAF, //
+            0xA6, 0x8E, 0x0F, 0xFF, 0x99, 0x14, 0x00, 0x04, 0x00, 0x00, //
+            0x03, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, //
+        ]);
+        header[0x1c..0x2c].fill(0xAA);
+
+        let parsed = super::parse_header(&header).unwrap();
+        assert_eq!(parsed.file_type, KugouFileType::KGM);
+        assert_eq!(parsed.header_size, 0x400);
+        assert_eq!(parsed.crypto_version, 3);
+        assert_eq!(parsed.key_slot, 2);
+        assert_eq!(parsed.file_key, [0xAA; 16]);
+        assert_eq!(parsed.audio_hash, None);
+
+        let mut decryptor = super::new_kgm(&table, &table, &table);
+        let err = decryptor.write(&header).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::KeyNotFound));
+
+        let slot_tables = KugouSlotTables::from([(2, KugouKeyTables::new(&table, &table, &table))]);
+        let mut decryptor = super::new_kgm_with_slots(slot_tables);
+        assert!(decryptor.write(&header).is_ok());
+
+        header[0x14] = 4;
+        let mut decryptor = super::new_kgm(&table, &table, &table);
+        let err = decryptor.write(&header).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::UnsupportedVersion));
+    }
+
+    const KGG_TEST_AUDIO_HASH: &str = "0123456789abcdef0123456789abcdef";
+
+    fn make_kgg_header(crypto_version: u32) -> Vec<u8> {
+        let mut header = vec![0u8; 0x400];
+        header[..16].copy_from_slice(&[
+            0x7C, 0xD5, 0x32, 0xEB, 0x86, 0x02, 0x7F, 0x4B, //
+            0xA8, 0xAF, 0xA6, 0x8E, 0x0F, 0xFF, 0x99, 0x14, //
+        ]);
+        header[0x10..0x14].copy_from_slice(&0x400u32.to_le_bytes());
+        header[0x14..0x18].copy_from_slice(&crypto_version.to_le_bytes());
+        header[0x44..0x48].copy_from_slice(&(KGG_TEST_AUDIO_HASH.len() as u32).to_le_bytes());
+        header[0x48..0x68].copy_from_slice(KGG_TEST_AUDIO_HASH.as_bytes());
+        header
+    }
+
+    fn kgg_test_lookup(audio_hash: &str) -> Option<String> {
+        if audio_hash == KGG_TEST_AUDIO_HASH {
+            Some(TEST_QMC_V2_RC4_EKEY.into())
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_kgg() {
+        let mut test_data = make_kgg_header(5);
+        let header = super::parse_header(&test_data).unwrap();
+        assert_eq!(header.audio_hash.as_deref(), Some(KGG_TEST_AUDIO_HASH));
+        assert!(super::parse_header(&test_data[..0x10]).is_err());
+        assert!(super::parse_header(&test_data[..0x50]).is_err());
+
+        test_data.extend(generate_test_data(TEST_SIZE_4MB, "qmcv2 rc4 cipher data"));
+
+        // Same key and payload as `qmc_v2::test::test_qmc_v2_rc4`.
+        let mut decryptor = super::new_kgg(kgg_test_lookup);
+        decryptor.set_recovery_mode(true);
+        let result = decrypt_test_content(&mut decryptor, test_data);
+        assert_eq!(
+            result,
+            "757fc9aa94ab48295b106a16452b7da7b90395be8e3132a077b6d2a9ea216838"
+        );
+        assert!(decryptor.get_damaged_ranges().is_empty());
+    }
+
+    #[test]
+    fn test_kgg_errors() {
+        let mut decryptor = super::new_kgg(kgg_test_lookup);
+        let err = decryptor.write(&make_kgg_header(3)).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::UnsupportedVersion));
+
+        let mut decryptor = super::new_kgg(|_: &str| None);
+        let err = decryptor.write(&make_kgg_header(5)).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::KeyNotFound));
+    }
 }
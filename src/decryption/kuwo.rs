@@ -1,75 +1,232 @@
+use crate::utils::audio::AudioType;
+
 const KUWO_DECRYPTION_KEY_SIZE: usize = 0x20;
 type KuwoKey = [u8; KUWO_DECRYPTION_KEY_SIZE];
 
+/// Look up the ekey of a v2 file by its resource id.
+type KuwoEKeyLookup = Box<dyn Fn(u64) -> Option<String>>;
+
+/// Size of the header preceding the encrypted audio data.
+pub const KUWO_HEADER_SIZE: usize = 0x400;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KuwoHeader {
+    pub version: u32,
+    pub resource_id: u64,
+    /// kbps, `0` if not present.
+    pub bitrate: u32,
+    /// Lowercase format hint, e.g. `mp3` or `flac`; empty if not present.
+    pub format: String,
+}
+
+impl KuwoHeader {
+    /// Audio type suggested by the format hint.
+    pub fn audio_type(&self) -> AudioType {
+        AudioType::from_extension(&self.format).unwrap_or(AudioType::UnknownType)
+    }
+
+    /// Cross-check the type detected from the decrypted audio (see `detect_audio_type`)
+    ///   against the format hint. Always `true` if either type is unknown.
+    pub fn matches_audio_type(&self, detected: &AudioType) -> bool {
+        let hint = self.audio_type();
+        match (hint, *detected) {
+            (AudioType::UnknownType, _) | (_, AudioType::UnknownType) => true,
+            // AAC is usually stored in a MP4 container.
+            (AudioType::AAC | AudioType::M4A, AudioType::AAC | AudioType::M4A | AudioType::MP4) => {
+                true
+            }
+            _ => hint == *detected,
+        }
+    }
+}
+
 mod detail {
-    use super::KuwoKey;
+    /**
+     * @brief Kuwo file format
+     *
+     *   0000h: "yeelion-kuwo-tme"
+     *   0010h: u32_le(version)
+     *   0018h: u64_le(resource_id)
+     *   0030h: char[8] bitrate & format hint, e.g. "320kmp3" (NUL padded)
+     *   0400h: encrypted audio data
+     *
+     * v1: XOR with `KuwoKey`, itself XOR-ed with the decimal resource id.
+     * v2: QMCv2 (map or RC4, by key length), the ekey is looked up by resource id.
+     */
+    use super::{KuwoEKeyLookup, KuwoHeader, KuwoKey, KUWO_HEADER_SIZE};
     use crate::{
-        decryptor::{BaseDecryptorData, DecryptError, DecryptErrorCode, Decryptor},
+        decryption::qmc::{new_qmc_v2_from_key, QMCv2Cipher},
+        decryptor::{BaseDecryptorData, BodyDecryptor, DecryptError, DecryptErrorCode, Decryptor},
         impl_decryptor_inner_helper,
-        utils::array_ext::ByteSliceExt,
+        tencent::qmc_footer::parse_ekey,
+        utils::{
+            array_ext::ByteSliceExt,
+            audio::{AudioType, AudioTypeSniffer},
+        },
     };
     use std::cmp::Ordering;
 
+    const VERSION_OFFSET: usize = 0x10;
     const FILE_KEY_OFFSET: usize = 0x18;
-    const FULL_HEADER_SIZE: usize = 0x400;
+    const FORMAT_OFFSET: usize = 0x30;
+    const FORMAT_SIZE: usize = 8;
+    const HEADER_PARSE_SIZE: usize = FORMAT_OFFSET + FORMAT_SIZE;
     const KUWO_MAGIC_HEADER: &[u8; 0x10] = &[
         0x79u8, 0x65, 0x65, 0x6c, 0x69, 0x6f, 0x6e, 0x2d, //
         0x6bu8, 0x75, 0x77, 0x6f, 0x2d, 0x74, 0x6d, 0x65, //
     ];
 
+    const KUWO_VERSION_1: u32 = 1;
+    const KUWO_VERSION_2: u32 = 2;
+
+    /// `buf` must hold at least the first 0x38 bytes of the file.
+    pub fn parse_header(buf: &[u8]) -> Result<KuwoHeader, DecryptError> {
+        if buf.len() < HEADER_PARSE_SIZE {
+            return Err(DecryptError::new(
+                DecryptErrorCode::InvalidBlockSize,
+                "kuwo header too small",
+            ));
+        }
+
+        if buf[..KUWO_MAGIC_HEADER.len()].cmp(KUWO_MAGIC_HEADER) != Ordering::Equal {
+            return Err(DecryptError::new(
+                DecryptErrorCode::UnknownMagicHeader,
+                "unknown magic header",
+            ));
+        }
+
+        // e.g. "320kmp3", "2000flac"
+        let hint = &buf[FORMAT_OFFSET..FORMAT_OFFSET + FORMAT_SIZE];
+        let hint = String::from_utf8_lossy(hint);
+        let hint = hint.trim_end_matches('\0');
+        let (bitrate, format) = hint.split_at(
+            hint.find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(hint.len()),
+        );
+        let format = format.strip_prefix(['k', 'K']).unwrap_or(format);
+
+        Ok(KuwoHeader {
+            version: buf.read_le::<u32>(VERSION_OFFSET),
+            resource_id: buf.read_le::<u64>(FILE_KEY_OFFSET),
+            bitrate: bitrate.parse().unwrap_or_default(),
+            format: format.to_ascii_lowercase(),
+        })
+    }
+
     enum State {
         WaitForHeader,
         Decrypt,
     }
 
-    struct KuwoDecryptor {
+    pub struct KuwoDecryptor {
         data: BaseDecryptorData,
         key: KuwoKey,
+        ekey_lookup: Option<KuwoEKeyLookup>,
+        qmc: Option<BodyDecryptor>,
         state: State,
+        header: Option<KuwoHeader>,
+        sniffer: AudioTypeSniffer,
     }
 
     impl KuwoDecryptor {
-        fn new(key: &KuwoKey) -> Self {
+        fn new(key: &KuwoKey, ekey_lookup: Option<KuwoEKeyLookup>) -> Self {
             Self {
                 data: BaseDecryptorData::new("Kuwo"),
                 key: *key,
+                ekey_lookup,
+                qmc: None,
                 state: State::WaitForHeader,
+                header: None,
+                sniffer: AudioTypeSniffer::new(),
             }
         }
 
-        fn init_key(&mut self) {
-            let resource_id = self.data.buf_in.read_le::<u64>(FILE_KEY_OFFSET);
-            let resource_id = resource_id.to_string();
-            self.key.xor_key(resource_id.as_bytes());
+        /// `None` until the header was read.
+        pub fn header(&self) -> Option<&KuwoHeader> {
+            self.header.as_ref()
         }
-    }
 
-    impl Decryptor for KuwoDecryptor {
-        impl_decryptor_inner_helper! {}
+        /// Type detected from the decrypted audio;
+        ///   `None` until enough was decrypted, or `end()` was called.
+        pub fn detected_audio_type(&self) -> Option<AudioType> {
+            self.sniffer.audio_type()
+        }
 
-        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        /// Whether the format hint in the header agrees with the decrypted audio,
+        ///   see `KuwoHeader::matches_audio_type`; `None` until both are known.
+        pub fn format_matches_header(&self) -> Option<bool> {
+            let detected = self.detected_audio_type()?;
+            Some(self.header()?.matches_audio_type(&detected))
+        }
+
+        fn init_key(&mut self, header: &KuwoHeader) -> Result<(), DecryptError> {
+            let resource_id = header.resource_id;
+
+            match header.version {
+                KUWO_VERSION_1 => {
+                    let resource_id = resource_id.to_string();
+                    self.key.xor_key(resource_id.as_bytes());
+                }
+
+                KUWO_VERSION_2 => {
+                    let ekey = self
+                        .ekey_lookup
+                        .as_ref()
+                        .and_then(|ekey_lookup| ekey_lookup(resource_id))
+                        .ok_or_else(|| {
+                            DecryptError::new(
+                                DecryptErrorCode::KeyNotFound,
+                                &format!("ekey not found for resource id {}", resource_id),
+                            )
+                        })?;
+
+                    let key = parse_ekey(&ekey)
+                        .filter(|key| !key.is_empty())
+                        .ok_or_else(|| {
+                            DecryptError::new(DecryptErrorCode::InvalidEKey, "invalid ekey")
+                        })?;
+
+                    let cipher = QMCv2Cipher::from_key_len(key.len());
+                    self.qmc = Some(BodyDecryptor::new(
+                        new_qmc_v2_from_key(key, 0, cipher),
+                        KUWO_HEADER_SIZE,
+                        self.data.recovery_mode,
+                    ));
+                }
+
+                _ => {
+                    return Err(DecryptError::new(
+                        DecryptErrorCode::UnsupportedVersion,
+                        &format!("unsupported kuwo version {}", header.version),
+                    ));
+                }
+            }
+
+            Ok(())
+        }
+
+        fn decrypt(&mut self, data: &[u8]) -> Result<(), DecryptError> {
             let mut p = data;
 
             while !p.is_empty() {
                 match self.state {
                     State::WaitForHeader => {
-                        if self.data.read_until_offset(&mut p, FULL_HEADER_SIZE) {
-                            if self.data.buf_in[..KUWO_MAGIC_HEADER.len()].cmp(KUWO_MAGIC_HEADER)
-                                != Ordering::Equal
-                            {
-                                return Err(DecryptError::new(
-                                    DecryptErrorCode::UnknownMagicHeader,
-                                    "unknown magic header",
-                                ));
-                            }
-
-                            self.init_key();
-                            self.data.seek_input(FULL_HEADER_SIZE);
+                        if self.data.read_until_offset(&mut p, KUWO_HEADER_SIZE) {
+                            let header = parse_header(&self.data.buf_in)?;
+                            self.init_key(&header)?;
+                            self.header = Some(header);
+                            self.data.seek_input(KUWO_HEADER_SIZE);
                             self.state = State::Decrypt;
                         }
                     }
 
                     State::Decrypt => {
+                        if let Some(qmc) = self.qmc.as_mut() {
+                            qmc.write(&mut self.data, p)?;
+                            self.data.offset += p.len();
+                            return Ok(());
+                        }
+
                         let size = p.len();
                         let mut out = Vec::from(p);
                         out.xor_key_with_key_offset(self.key, self.data.offset);
@@ -84,16 +241,59 @@ mod detail {
         }
     }
 
-    pub fn new_kwm(key: &KuwoKey) -> impl Decryptor {
-        KuwoDecryptor::new(key)
+    impl Decryptor for KuwoDecryptor {
+        impl_decryptor_inner_helper! {}
+
+        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            let out_len = self.data.buf_out.len();
+            self.decrypt(data)?;
+            self.sniffer.write(&self.data.buf_out[out_len..]);
+            Ok(())
+        }
+
+        fn end(&mut self) -> Result<(), DecryptError> {
+            if let Some(qmc) = self.qmc.as_mut() {
+                let out_len = self.data.buf_out.len();
+                qmc.end(&mut self.data)?;
+                self.sniffer.write(&self.data.buf_out[out_len..]);
+            }
+            self.sniffer.end();
+            Ok(())
+        }
+    }
+
+    /// Decrypt v1 files only; v2 files fail with `KeyNotFound`.
+    pub fn new_kwm(key: &KuwoKey) -> KuwoDecryptor {
+        KuwoDecryptor::new(key, None)
+    }
+
+    /// Decrypt v1 files with `key`, and v2 files with the ekey from `ekey_lookup`.
+    pub fn new_kwm_with_ekey<F: Fn(u64) -> Option<String> + 'static>(
+        key: &KuwoKey,
+        ekey_lookup: F,
+    ) -> KuwoDecryptor {
+        KuwoDecryptor::new(key, Some(Box::new(ekey_lookup)))
     }
 }
 
 pub use detail::new_kwm;
+pub use detail::new_kwm_with_ekey;
+pub use detail::parse_header;
+pub use detail::KuwoDecryptor;
 
 #[cfg(test)]
 mod test {
-    use crate::utils::test_util::test::{decrypt_test_content, generate_test_data, TEST_SIZE_4MB};
+    use super::KuwoHeader;
+    use crate::{
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::{
+            array_ext::ByteSliceExt,
+            audio::{detect_audio_type, AudioType},
+            test_util::test::{
+                decrypt_test_content, generate_test_data, TEST_QMC_V2_RC4_EKEY, TEST_SIZE_4MB,
+            },
+        },
+    };
 
     #[test]
     fn test_kwm() {
@@ -113,4 +313,112 @@ mod test {
             "aefad6b6f75ecb915fd0211f02eeacbd9c28e51b22c06c6d1bb3c61c963feaae"
         );
     }
+
+    const KWM_V2_TEST_RESOURCE_ID: u64 = 0x3322_11DD_EEFF;
+
+    fn make_kwm_header(version: u32) -> Vec<u8> {
+        let mut header = vec![0u8; 0x400];
+        header[..0x10].copy_from_slice(b"yeelion-kuwo-tme");
+        header[0x10..0x14].copy_from_slice(&version.to_le_bytes());
+        header[0x18..0x20].copy_from_slice(&KWM_V2_TEST_RESOURCE_ID.to_le_bytes());
+        header[0x30..0x37].copy_from_slice(b"320kmp3");
+        header
+    }
+
+    fn kwm_test_lookup(resource_id: u64) -> Option<String> {
+        if resource_id == KWM_V2_TEST_RESOURCE_ID {
+            Some(TEST_QMC_V2_RC4_EKEY.into())
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_kwm_v2() {
+        let test_key = generate_test_data(super::KUWO_DECRYPTION_KEY_SIZE, "kuwo-test-key");
+        let mut test_data = make_kwm_header(2);
+        test_data.extend(generate_test_data(TEST_SIZE_4MB, "qmcv2 rc4 cipher data"));
+
+        // Same key and payload as `qmc_v2::test::test_qmc_v2_rc4`.
+        let mut decryptor =
+            super::new_kwm_with_ekey(test_key[..].try_into().unwrap(), kwm_test_lookup);
+        decryptor.set_recovery_mode(true);
+        let result = decrypt_test_content(&mut decryptor, test_data);
+        assert_eq!(
+            result,
+            "757fc9aa94ab48295b106a16452b7da7b90395be8e3132a077b6d2a9ea216838"
+        );
+        assert!(decryptor.get_damaged_ranges().is_empty());
+    }
+
+    #[test]
+    fn test_kwm_version_errors() {
+        let test_key = generate_test_data(super::KUWO_DECRYPTION_KEY_SIZE, "kuwo-test-key");
+        let test_key = test_key[..].try_into().unwrap();
+
+        let mut decryptor = super::new_kwm(test_key);
+        let err = decryptor.write(&make_kwm_header(2)).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::KeyNotFound));
+
+        let mut decryptor = super::new_kwm_with_ekey(test_key, kwm_test_lookup);
+        let err = decryptor.write(&make_kwm_header(3)).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::UnsupportedVersion));
+        assert!(format!("{:?}", err).contains("version 3"));
+    }
+
+    #[test]
+    fn test_kwm_header() {
+        let header = super::parse_header(&make_kwm_header(2)).unwrap();
+        assert_eq!(
+            header,
+            KuwoHeader {
+                version: 2,
+                resource_id: KWM_V2_TEST_RESOURCE_ID,
+                bitrate: 320,
+                format: "mp3".into(),
+            }
+        );
+        assert!(header.matches_audio_type(&AudioType::MP3));
+        assert!(!header.matches_audio_type(&AudioType::FLAC));
+
+        let mut buf = make_kwm_header(1);
+        buf[0x30..0x38].copy_from_slice(b"2000FLAC");
+        let header = super::parse_header(&buf).unwrap();
+        assert_eq!((header.bitrate, header.format.as_str()), (2000, "flac"));
+        assert!(
+            header.matches_audio_type(&detect_audio_type(b"fLaC\0\0\0\x22\x12\0\x12\0\0\0\0\0"))
+        );
+
+        assert!(super::parse_header(&buf[..0x20]).is_err());
+    }
+
+    #[test]
+    fn test_kwm_decryptor_header() {
+        let test_key = generate_test_data(super::KUWO_DECRYPTION_KEY_SIZE, "kuwo-test-key");
+        let mut key = test_key.clone();
+        key.xor_key(KWM_V2_TEST_RESOURCE_ID.to_string().as_bytes());
+
+        let mut plain = b"fLaC\0\0\0\x22\x12\0\x12\0\0\0\0\0".to_vec();
+        plain.resize(0x100, 0);
+        let mut encrypted = plain.clone();
+        encrypted.xor_key_with_key_offset(&key, super::KUWO_HEADER_SIZE);
+
+        for (hint, matches) in [(&b"2000flac"[..], true), (b"320kmp3\0", false)] {
+            let mut test_data = make_kwm_header(1);
+            test_data[0x30..0x38].copy_from_slice(hint);
+            test_data.extend_from_slice(&encrypted);
+
+            let mut decryptor = super::new_kwm(test_key[..].try_into().unwrap());
+            assert!(decryptor.header().is_none());
+            decryptor.write(&test_data[..0x400]).unwrap();
+            assert_eq!(decryptor.header().unwrap().version, 1);
+            assert_eq!(decryptor.format_matches_header(), None);
+
+            decryptor.write(&test_data[0x400..]).unwrap();
+            decryptor.end().unwrap();
+            assert_eq!(decryptor.read_all_output(), plain);
+            assert_eq!(decryptor.detected_audio_type(), Some(AudioType::FLAC));
+            assert_eq!(decryptor.format_matches_header(), Some(matches));
+        }
+    }
 }
@@ -2,26 +2,55 @@ pub mod joox;
 pub mod kugou;
 pub mod kuwo;
 pub mod ncm;
+pub mod ncm_cache;
+pub mod output_type;
+pub mod xiami;
 pub mod ximalaya;
 
 mod qmc;
 mod qmc_v1;
 mod qmc_v2;
 
+// Joox
+pub use joox::new_joox;
+
 // Kugou
+pub use kugou::new_kgg;
 pub use kugou::new_kgm;
+pub use kugou::new_kgm_with_slots;
 pub use kugou::new_vpr;
+pub use kugou::new_vpr_with_slots;
 
 // Kuwo
 pub use kuwo::new_kwm;
+pub use kuwo::new_kwm_with_ekey;
+pub use kuwo::{KuwoDecryptor, KuwoHeader, KUWO_HEADER_SIZE};
 
 // Netease
 pub use ncm::new_ncm;
+pub use ncm::new_ncm_with_options;
+pub use ncm::{NCMCoverEvent, NCMOptions};
+pub use ncm_cache::new_ncm_cache;
+
+// Output type detection
+pub use output_type::new_output_type_detector;
+pub use output_type::OutputTypeDetector;
 
 // Tencent QQMusic (QMC)
 pub use qmc::new_qmc_v2;
+pub use qmc::new_qmc_v2_auto;
+pub use qmc::new_qmc_v2_with_cipher;
+pub use qmc::{QMCKeySource, QMCVariant, QMCv2Cipher};
 pub use qmc_v1::new_qmc_v1_static as new_qmc_v1;
 
+// Xiami
+pub use xiami::new_xiami;
+pub use xiami::{XiamiDecryptor, XiamiHeader};
+
 // Ximalaya
 pub use ximalaya::new_x2m;
 pub use ximalaya::new_x3m;
+pub use ximalaya::new_xm;
+pub use ximalaya::parse_xm_header;
+pub use ximalaya::ScrambleTable;
+pub use ximalaya::XMHeader;
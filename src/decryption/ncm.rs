@@ -1,7 +1,71 @@
+use crate::{
+    decryptor::DecryptError,
+    netease::music_info::{NCMMetaKey, NCMMusicInfo},
+    utils::image::ImageInfo,
+};
+
 // AES-128
 const NCM_DECRYPTION_KEY_SIZE: usize = 128 / 8;
 type NCMAudioKey = [u8; NCM_DECRYPTION_KEY_SIZE];
 
+pub enum NCMCoverEvent<'a> {
+    /// Size of the cover image in bytes, sent before any data.
+    Begin(usize),
+    /// Next chunk of the cover image.
+    Data(&'a [u8]),
+    /// Image type and dimensions, sniffed from the data.
+    End(ImageInfo),
+}
+
+type NCMMetadataCallback = Box<dyn FnMut(Result<&NCMMusicInfo, &DecryptError>)>;
+type NCMCoverCallback = Box<dyn FnMut(NCMCoverEvent)>;
+
+/// Optional behaviour of the NCM decryptor.
+#[derive(Default)]
+pub struct NCMOptions {
+    meta_key: Option<NCMMetaKey>,
+    on_metadata: Option<NCMMetadataCallback>,
+    on_cover: Option<NCMCoverCallback>,
+    embed_tags: bool,
+}
+
+impl NCMOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decrypt the metadata block with `meta_key`,
+    ///   and pass the parsed result to `on_metadata` before any audio is emitted.
+    /// A metadata block that fails to decrypt or parse is reported as `Err`;
+    ///   audio decryption continues regardless.
+    pub fn with_metadata<F: FnMut(Result<&NCMMusicInfo, &DecryptError>) + 'static>(
+        mut self,
+        meta_key: &NCMMetaKey,
+        on_metadata: F,
+    ) -> Self {
+        self.meta_key = Some(*meta_key);
+        self.on_metadata = Some(Box::new(on_metadata));
+        self
+    }
+
+    /// Stream the embedded album cover to `on_cover` as it is read,
+    ///   without buffering it.
+    pub fn with_cover<F: FnMut(NCMCoverEvent) + 'static>(mut self, on_cover: F) -> Self {
+        self.on_cover = Some(Box::new(on_cover));
+        self
+    }
+
+    /// Write the decrypted metadata and album cover into the audio output:
+    ///   an ID3v2 tag for MP3, or Vorbis comment & PICTURE blocks for FLAC.
+    /// Existing tags are kept; only missing fields are added.
+    /// The cover is buffered in memory until audio decryption starts.
+    pub fn with_embedded_tags(mut self, meta_key: &NCMMetaKey) -> Self {
+        self.meta_key = Some(*meta_key);
+        self.embed_tags = true;
+        self
+    }
+}
+
 mod detail {
     /**
      * @brief NCM file format
@@ -11,17 +75,22 @@ mod detail {
      *
      * Followed by 3 blocks:
      *   - Content Key (Encrypted using `NCMAudioKey`)
-     *   - Metadata; (AES-128 Encrypted, ignored by this library)
-     *   - Album Cover (prefixed with 5 bytes padding? ignored by this library);
+     *   - Metadata; (AES-128 Encrypted, see `NCMOptions::with_metadata`)
+     *   - Album Cover (prefixed with 5 bytes padding? see `NCMOptions::with_cover`);
      *   - Audio Data (Encrypted with Content Key);
+     *
+     * Metadata and cover can be written to the audio output, see `NCMOptions::with_embedded_tags`.
      */
-    use super::NCMAudioKey;
+    use super::{NCMAudioKey, NCMCoverEvent, NCMOptions};
     use crate::{
         decryptor::{BaseDecryptorData, DecryptError, DecryptErrorCode, Decryptor},
         impl_decryptor_inner_helper,
+        netease::music_info::parse_ncm_metadata,
         utils::{
             aes_ecb::Aes128EcbDec,
             array_ext::ByteSliceExt,
+            audio::{AudioCover, AudioTags, TagWriter},
+            image::ImageSniffer,
             rc4::{RC4Derive, RC4Netease},
         },
     };
@@ -35,13 +104,15 @@ mod detail {
     // cspell:disable-next-line
     const CONTENT_KEY_PREFIX: &[u8; 17] = b"neteasecloudmusic";
     const CONTENT_KEY_PREFIX_LEN: usize = CONTENT_KEY_PREFIX.len();
+    const META_BLOCK_PADDING: usize = 5;
 
     enum State {
         ReadFileHeader,
         ParseFileKey,
         ReadMetaBlock,
         ReadCoverFrameSize,
-        ReadCoverBlock,
+        ReadCoverSize,
+        ReadCoverBlock(usize),
         SkipCoverPadding(usize),
         DecryptAudio,
     }
@@ -51,12 +122,18 @@ mod detail {
         state: State,
         master_decryption_key: NCMAudioKey,
         audio_decryption_key: [u8; 0x100],
+        options: NCMOptions,
 
         content_key_size: usize,
         metadata_size: usize,
         cover_frame_size: usize,
         cover_size: usize,
+        cover_sniffer: ImageSniffer,
         audio_offset: usize,
+
+        tags: AudioTags,
+        cover: Vec<u8>,
+        tag_writer: Option<TagWriter>,
     }
 
     impl Decryptor for NeteaseDecryptor {
@@ -93,9 +170,13 @@ mod detail {
 
                     State::ReadMetaBlock => {
                         let ok: bool;
-                        (ok, self.metadata_size) =
-                            self.read_next_sized_block(&mut p, self.metadata_size, 5)?;
+                        (ok, self.metadata_size) = self.read_next_sized_block(
+                            &mut p,
+                            self.metadata_size,
+                            META_BLOCK_PADDING,
+                        )?;
                         if ok {
+                            self.parse_metadata();
                             self.data.seek_input(self.metadata_size);
                             self.state = State::ReadCoverFrameSize;
                         }
@@ -106,37 +187,67 @@ mod detail {
                             self.cover_frame_size =
                                 self.data.consume_input(size_of::<u32>()).read_le::<u32>(0)
                                     as usize;
-                            self.state = State::ReadCoverBlock;
+                            self.state = State::ReadCoverSize;
                         }
                     }
 
-                    State::ReadCoverBlock => {
-                        let ok: bool;
-                        (ok, self.cover_size) =
-                            self.read_next_sized_block(&mut p, self.cover_size, 0)?;
-                        if ok {
+                    State::ReadCoverSize => {
+                        if self.data.read_block(&mut p, size_of::<u32>()) {
+                            self.cover_size =
+                                self.data.consume_input(size_of::<u32>()).read_le::<u32>(0)
+                                    as usize;
                             if self.cover_frame_size < self.cover_size {
-                                return Err(DecryptError::new(
+                                let error = DecryptError::new(
                                     DecryptErrorCode::NCMCoverFrameTooSmall,
                                     "cover_frame too small",
-                                ));
+                                );
+                                // Best guess: trust the frame size, so audio starts in the right place.
+                                self.data.recover_or_fail(
+                                    self.get_cover_offset(),
+                                    self.cover_frame_size,
+                                    error,
+                                )?;
+                                self.cover_size = self.cover_frame_size;
                             }
 
-                            self.data.seek_input(self.cover_size);
+                            self.emit_cover_event(NCMCoverEvent::Begin(self.cover_size));
+                            self.state = State::ReadCoverBlock(self.cover_size);
+                        }
+                    }
+
+                    State::ReadCoverBlock(0) => {
+                        let info = self.cover_sniffer.info();
+                        self.emit_cover_event(NCMCoverEvent::End(info));
+                        if !self.cover.is_empty() {
+                            let data = std::mem::take(&mut self.cover);
+                            self.tags.cover = Some(AudioCover { data, info });
+                        }
+                        self.state =
+                            State::SkipCoverPadding(self.cover_frame_size - self.cover_size);
+                    }
 
-                            self.state =
-                                State::SkipCoverPadding(self.cover_frame_size - self.cover_size);
+                    State::ReadCoverBlock(n) => {
+                        let (cover, left_over) = p.split_at(usize::min(n, p.len()));
+                        p = left_over;
+
+                        if self.options.on_cover.is_some() || self.options.embed_tags {
+                            self.cover_sniffer.write(cover);
+                            self.emit_cover_event(NCMCoverEvent::Data(cover));
+                        }
+                        if self.options.embed_tags {
+                            self.cover.extend_from_slice(cover);
                         }
+                        self.state = State::ReadCoverBlock(n - cover.len());
                     }
 
                     State::SkipCoverPadding(0) => {
-                        self.state = State::DecryptAudio;
+                        self.begin_audio();
                     }
 
                     State::SkipCoverPadding(n) => {
                         if self.data.read_block(&mut p, n) {
                             self.data.seek_input(n);
-                            self.state = State::DecryptAudio;
+                            self.begin_audio();
                         }
                     }
 
@@ -144,7 +255,10 @@ mod detail {
                         let size = p.len();
                         let mut out = Vec::from(p);
                         out.xor_key_with_key_offset(self.audio_decryption_key, self.audio_offset);
-                        self.data.buf_out.append(&mut out);
+                        match &mut self.tag_writer {
+                            Some(tag_writer) => tag_writer.write(&out, &mut self.data.buf_out),
+                            None => self.data.buf_out.append(&mut out),
+                        }
                         self.data.offset += size;
                         self.audio_offset += size;
                         return Ok(());
@@ -154,23 +268,43 @@ mod detail {
 
             Ok(())
         }
+
+        fn end(&mut self) -> Result<(), DecryptError> {
+            if let Some(tag_writer) = &mut self.tag_writer {
+                tag_writer.end(&mut self.data.buf_out);
+            }
+            Ok(())
+        }
     }
 
     impl NeteaseDecryptor {
-        fn new(master_audio_key: &NCMAudioKey) -> Self {
+        fn new(master_audio_key: &NCMAudioKey, options: NCMOptions) -> Self {
             Self {
                 data: BaseDecryptorData::new("Netease"),
                 master_decryption_key: *master_audio_key,
+                options,
                 state: State::ReadFileHeader,
                 audio_decryption_key: [0u8; 0x100],
                 content_key_size: 0,
                 metadata_size: 0,
                 cover_frame_size: 0,
                 cover_size: 0,
+                cover_sniffer: ImageSniffer::new(),
                 audio_offset: 0,
+                tags: AudioTags::default(),
+                cover: vec![],
+                tag_writer: None,
             }
         }
 
+        fn begin_audio(&mut self) {
+            if self.options.embed_tags {
+                let tags = std::mem::take(&mut self.tags);
+                self.tag_writer = Some(TagWriter::new(tags));
+            }
+            self.state = State::DecryptAudio;
+        }
+
         fn read_next_sized_block(
             &mut self,
             data: &mut &[u8],
@@ -194,6 +328,48 @@ mod detail {
             Ok((ok, next_block_size))
         }
 
+        /// Metadata errors never stop the audio decryption, see `NCMOptions::with_metadata`.
+        fn parse_metadata(&mut self) {
+            let metadata_len = self.metadata_size - META_BLOCK_PADDING;
+            if metadata_len == 0 {
+                return;
+            }
+
+            let wants_metadata = self.options.on_metadata.is_some() || self.options.embed_tags;
+            let Some(meta_key) = self.options.meta_key.as_ref().filter(|_| wants_metadata) else {
+                return;
+            };
+
+            let result = parse_ncm_metadata(&self.data.buf_in[..metadata_len], meta_key);
+            if let Some(on_metadata) = &mut self.options.on_metadata {
+                on_metadata(result.as_ref());
+            }
+            if let (true, Ok(info)) = (self.options.embed_tags, result) {
+                self.tags.title = info.title;
+                self.tags.artists = info.artists;
+                self.tags.album = info.album;
+            }
+        }
+
+        /// Offset of the cover image in the file.
+        #[inline]
+        fn get_cover_offset(&self) -> usize {
+            // header, u32(key_size) key, u32(meta_size) meta padding, u32(frame) u32(size)
+            INITIAL_HEADER_LEN
+                + size_of::<u32>()
+                + self.content_key_size
+                + size_of::<u32>()
+                + self.metadata_size
+                + size_of::<u32>() * 2
+        }
+
+        #[inline]
+        fn emit_cover_event(&mut self, event: NCMCoverEvent) {
+            if let Some(on_cover) = &mut self.options.on_cover {
+                on_cover(event);
+            }
+        }
+
         fn parse_key(&mut self) -> Result<(), DecryptError> {
             let mut encrypted_content_key = self.data.consume_input(self.content_key_size);
             for v in encrypted_content_key.iter_mut() {
@@ -234,54 +410,76 @@ mod detail {
     }
 
     pub fn new_ncm(key: &NCMAudioKey) -> impl Decryptor {
-        NeteaseDecryptor::new(key)
+        NeteaseDecryptor::new(key, NCMOptions::default())
+    }
+
+    pub fn new_ncm_with_options(key: &NCMAudioKey, options: NCMOptions) -> impl Decryptor {
+        NeteaseDecryptor::new(key, options)
     }
 }
 
 pub use detail::new_ncm;
+pub use detail::new_ncm_with_options;
 
 #[cfg(test)]
 mod test {
+    use std::{cell::RefCell, rc::Rc};
+
     use crate::{
-        decryption::ncm::NCM_DECRYPTION_KEY_SIZE,
-        utils::test_util::test::{decrypt_test_content, generate_test_data, TEST_SIZE_4MB},
+        decryption::ncm::{NCMCoverEvent, NCMOptions, NCM_DECRYPTION_KEY_SIZE},
+        decryptor::{DecryptErrorCode, Decryptor},
+        netease::music_info::{
+            test::{make_163_key, make_test_meta_key},
+            NCMMusicInfo,
+        },
+        utils::{
+            image::ImageType,
+            test_util::test::{decrypt_test_content, generate_test_data, TEST_SIZE_4MB},
+        },
     };
 
+    const TEST_NCM_KEY_HEADER: &[u8] = &[
+        0x43, 0x54, 0x45, 0x4E, 0x46, 0x44, 0x41, 0x4D, // header
+        0xff, 0xff, // padding
+        0x90, 0x00, 0x00, 0x00, // key size
+        // key:
+        //    neteasecloudmusic625064132972419780152239073outTde996wZqM
+        //    k9R2NAS0zMZ9fHd4z37ei2drOBpNEYWFiN0jMiujKyv7pXPkxtj8eTck0
+        //    0Jixun0Parakeet
+        0x4D, 0x3C, 0x5A, 0x96, 0x74, 0x42, 0x64, 0xD3, 0x14, 0x4F, 0x77, 0xBB, //
+        0x3C, 0x7B, 0x60, 0x56, 0x96, 0xA0, 0xD0, 0x12, 0xCB, 0xB8, 0xB6, 0x86, //
+        0x13, 0xE6, 0xEF, 0x51, 0x00, 0x7E, 0xED, 0x02, 0xDF, 0xFE, 0xD2, 0xED, //
+        0x6C, 0x4A, 0xA1, 0x33, 0x0C, 0xEA, 0x8E, 0x00, 0x3A, 0xBC, 0xAA, 0xFB, //
+        0x47, 0xAC, 0xE3, 0x0B, 0xBA, 0xEA, 0xA4, 0x88, 0x6D, 0x84, 0x74, 0xBE, //
+        0x28, 0x86, 0x1B, 0x43, 0xF7, 0x2F, 0x2A, 0xFD, 0x85, 0x44, 0xA0, 0xFC, //
+        0xCD, 0xE0, 0xD7, 0xEC, 0x8F, 0xDB, 0xB0, 0xB5, 0x39, 0xFD, 0x94, 0x27, //
+        0xD5, 0x24, 0x98, 0xCE, 0x2E, 0x6B, 0x7C, 0xBB, 0x16, 0x55, 0x0B, 0x63, //
+        0x63, 0x3E, 0x8E, 0x26, 0x91, 0xF9, 0x32, 0x37, 0x38, 0xC0, 0x93, 0xD9, //
+        0xCF, 0x40, 0x44, 0x5A, 0x6E, 0xDE, 0xEA, 0xCA, 0x27, 0xCB, 0x50, 0x54, //
+        0x12, 0xFE, 0x12, 0x89, 0x59, 0x06, 0x72, 0xA9, 0x81, 0x33, 0x1F, 0xBE, //
+        0xCB, 0xC9, 0x38, 0xFA, 0xE8, 0x94, 0xEB, 0xD7, 0x04, 0xF2, 0x58, 0xB4, //
+    ];
+
     #[test]
     fn test_ncm() {
         let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
 
         let mut test_data = generate_test_data(TEST_SIZE_4MB, "ncm-test-data");
-        let header_override: &[u8] = &[
-            0x43, 0x54, 0x45, 0x4E, 0x46, 0x44, 0x41, 0x4D, // header
-            0xff, 0xff, // padding
-            0x90, 0x00, 0x00, 0x00, // key size
-            // key:
-            //    neteasecloudmusic625064132972419780152239073outTde996wZqM
-            //    k9R2NAS0zMZ9fHd4z37ei2drOBpNEYWFiN0jMiujKyv7pXPkxtj8eTck0
-            //    0Jixun0Parakeet
-            0x4D, 0x3C, 0x5A, 0x96, 0x74, 0x42, 0x64, 0xD3, 0x14, 0x4F, 0x77, 0xBB, //
-            0x3C, 0x7B, 0x60, 0x56, 0x96, 0xA0, 0xD0, 0x12, 0xCB, 0xB8, 0xB6, 0x86, //
-            0x13, 0xE6, 0xEF, 0x51, 0x00, 0x7E, 0xED, 0x02, 0xDF, 0xFE, 0xD2, 0xED, //
-            0x6C, 0x4A, 0xA1, 0x33, 0x0C, 0xEA, 0x8E, 0x00, 0x3A, 0xBC, 0xAA, 0xFB, //
-            0x47, 0xAC, 0xE3, 0x0B, 0xBA, 0xEA, 0xA4, 0x88, 0x6D, 0x84, 0x74, 0xBE, //
-            0x28, 0x86, 0x1B, 0x43, 0xF7, 0x2F, 0x2A, 0xFD, 0x85, 0x44, 0xA0, 0xFC, //
-            0xCD, 0xE0, 0xD7, 0xEC, 0x8F, 0xDB, 0xB0, 0xB5, 0x39, 0xFD, 0x94, 0x27, //
-            0xD5, 0x24, 0x98, 0xCE, 0x2E, 0x6B, 0x7C, 0xBB, 0x16, 0x55, 0x0B, 0x63, //
-            0x63, 0x3E, 0x8E, 0x26, 0x91, 0xF9, 0x32, 0x37, 0x38, 0xC0, 0x93, 0xD9, //
-            0xCF, 0x40, 0x44, 0x5A, 0x6E, 0xDE, 0xEA, 0xCA, 0x27, 0xCB, 0x50, 0x54, //
-            0x12, 0xFE, 0x12, 0x89, 0x59, 0x06, 0x72, 0xA9, 0x81, 0x33, 0x1F, 0xBE, //
-            0xCB, 0xC9, 0x38, 0xFA, 0xE8, 0x94, 0xEB, 0xD7, 0x04, 0xF2, 0x58, 0xB4, //
-            // Image metadata Block
-            0x03, 0x00, 0x00, 0x00, //
-            0x01, 0x02, 0x03, //
-            0xff, 0xff, 0xff, 0xff, 0xff, // Unknown padding
-            //
-            0x03, 0x00, 0x00, 0x00, // Image Cover Frame
-            0x03, 0x00, 0x00, 0x00, // Image Cover Size
-            0xff, 0xff, 0xff, // Image Cover data
-        ];
-        test_data[..header_override.len()].copy_from_slice(header_override);
+        let header_override = [
+            TEST_NCM_KEY_HEADER,
+            &[
+                // Image metadata Block
+                0x03, 0x00, 0x00, 0x00, //
+                0x01, 0x02, 0x03, //
+                0xff, 0xff, 0xff, 0xff, 0xff, // Unknown padding
+                //
+                0x03, 0x00, 0x00, 0x00, // Image Cover Frame
+                0x03, 0x00, 0x00, 0x00, // Image Cover Size
+                0xff, 0xff, 0xff, // Image Cover data
+            ],
+        ]
+        .concat();
+        test_data[..header_override.len()].copy_from_slice(&header_override);
 
         let mut decryptor = super::new_ncm(test_key[..].try_into().unwrap());
         let result = decrypt_test_content(&mut decryptor, test_data);
@@ -290,4 +488,209 @@ mod test {
             "dae77d29821092561702e3cde97add3558f21a1607c9aab0599983632ce0d54b"
         );
     }
+
+    fn make_ncm_file(meta_block: &[u8], cover: &[u8], audio: &[u8]) -> Vec<u8> {
+        let mut test_data = Vec::from(TEST_NCM_KEY_HEADER);
+        test_data.extend_from_slice(&(meta_block.len() as u32).to_le_bytes());
+        test_data.extend_from_slice(meta_block);
+        test_data.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0xff]); // Unknown padding
+        test_data.extend_from_slice(&(cover.len() as u32 + 2).to_le_bytes()); // Image Cover Frame
+        test_data.extend_from_slice(&(cover.len() as u32).to_le_bytes()); // Image Cover Size
+        test_data.extend_from_slice(cover);
+        test_data.extend_from_slice(&[0xff, 0xff]); // Image Cover Frame padding
+        test_data.extend_from_slice(audio);
+        test_data
+    }
+
+    #[test]
+    fn test_ncm_metadata() {
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let meta_key = make_test_meta_key();
+
+        let comment = make_163_key(
+            r#"music:{"musicId":1,"musicName":"Parakeet","artist":[["Jixun",2]],"format":"flac"}"#,
+            &meta_key,
+        );
+        let meta_block: Vec<u8> = comment.bytes().map(|v| v ^ 0x63).collect();
+
+        let audio = generate_test_data(0x100, "ncm-test-data");
+        let test_data = make_ncm_file(&meta_block, &[0xff, 0xff, 0xff], &audio);
+
+        let received: Rc<RefCell<Option<NCMMusicInfo>>> = Rc::new(RefCell::new(None));
+        let received_cb = received.clone();
+        let options = NCMOptions::new().with_metadata(&meta_key, move |info| {
+            received_cb.replace(Some(info.unwrap().clone()));
+        });
+
+        let mut decryptor = super::new_ncm_with_options(test_key[..].try_into().unwrap(), options);
+        decrypt_test_content(&mut decryptor, test_data);
+
+        let info = received.take().expect("metadata callback should be called");
+        assert_eq!(info.music_id, 1);
+        assert_eq!(info.title, "Parakeet");
+        assert_eq!(info.artists, vec!["Jixun".to_string()]);
+        assert_eq!(info.format, "flac");
+    }
+
+    #[test]
+    fn test_ncm_invalid_metadata() {
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let meta_key = make_test_meta_key();
+        let audio = generate_test_data(0x100, "ncm-test-data");
+
+        // "163 key(Don't modify):" followed by invalid base64.
+        let meta_block: Vec<u8> = b"163 key(Don't modify):!!not base64!!"
+            .iter()
+            .map(|v| v ^ 0x63)
+            .collect();
+        let test_data = make_ncm_file(&meta_block, &[0xff, 0xff, 0xff], &audio);
+
+        let mut decryptor = super::new_ncm(test_key[..].try_into().unwrap());
+        decryptor.write(&test_data).unwrap();
+        let expected = decryptor.read_all_output();
+
+        let received: Rc<RefCell<Option<bool>>> = Rc::new(RefCell::new(None));
+        let received_cb = received.clone();
+        let options = NCMOptions::new().with_metadata(&meta_key, move |info| {
+            received_cb.replace(Some(info.is_ok()));
+        });
+
+        let mut decryptor = super::new_ncm_with_options(test_key[..].try_into().unwrap(), options);
+        decryptor.write(&test_data).unwrap();
+        decryptor.end().unwrap();
+        assert_eq!(decryptor.read_all_output(), expected);
+        assert_eq!(received.take(), Some(false));
+
+        // Embedding tags is skipped, the audio is left as is.
+        let options = NCMOptions::new().with_embedded_tags(&meta_key);
+        let mut decryptor = super::new_ncm_with_options(test_key[..].try_into().unwrap(), options);
+        decryptor.write(&test_data).unwrap();
+        decryptor.end().unwrap();
+        assert_eq!(decryptor.read_all_output(), expected);
+    }
+
+    #[test]
+    fn test_ncm_cover() {
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let mut cover = vec![
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, // signature
+            0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52, // IHDR
+            0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x00, // 512x512
+        ];
+        cover.extend(generate_test_data(0x1000, "ncm-test-cover"));
+        let audio = generate_test_data(0x100, "ncm-test-data");
+        let test_data = make_ncm_file(&[0x01, 0x02, 0x03], &cover, &audio);
+
+        let received = Rc::new(RefCell::new((0usize, vec![], None)));
+        let received_cb = received.clone();
+        let options = NCMOptions::new().with_cover(move |event| {
+            let mut received = received_cb.borrow_mut();
+            match event {
+                NCMCoverEvent::Begin(size) => received.0 = size,
+                NCMCoverEvent::Data(data) => received.1.extend_from_slice(data),
+                NCMCoverEvent::End(info) => received.2 = Some(info),
+            }
+        });
+
+        let mut decryptor = super::new_ncm_with_options(test_key[..].try_into().unwrap(), options);
+        for chunk in test_data.chunks(0x100) {
+            decryptor.write(chunk).unwrap();
+        }
+
+        let (size, data, info) = received.take();
+        assert_eq!(size, cover.len());
+        assert_eq!(data, cover);
+
+        let info = info.expect("cover end event should be sent");
+        assert_eq!(info.image_type, ImageType::PNG);
+        assert_eq!((info.width, info.height), (512, 512));
+    }
+
+    #[test]
+    fn test_ncm_embedded_tags() {
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let meta_key = make_test_meta_key();
+
+        let comment = make_163_key(
+            r#"music:{"musicId":1,"musicName":"Parakeet","artist":[["Jixun",2]],"album":"Album"}"#,
+            &meta_key,
+        );
+        let meta_block: Vec<u8> = comment.bytes().map(|v| v ^ 0x63).collect();
+        let cover = [0xFF, 0xD8, 0xFF, 0xD9];
+
+        // Encrypt an MP3 frame with the key stream recovered from zeroes.
+        let mut audio = vec![0u8; 0x100];
+        let mut decryptor = super::new_ncm(test_key[..].try_into().unwrap());
+        decryptor
+            .write(&make_ncm_file(&meta_block, &cover, &audio))
+            .unwrap();
+        let key_stream = decryptor.read_all_output();
+        audio[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+        let plain_audio = audio.clone();
+        for (v, k) in audio.iter_mut().zip(key_stream.iter()) {
+            *v ^= k;
+        }
+
+        let options = NCMOptions::new().with_embedded_tags(&meta_key);
+        let mut decryptor = super::new_ncm_with_options(test_key[..].try_into().unwrap(), options);
+        for chunk in make_ncm_file(&meta_block, &cover, &audio).chunks(0x10) {
+            decryptor.write(chunk).unwrap();
+        }
+        decryptor.end().unwrap();
+        let output = decryptor.read_all_output();
+
+        assert!(output.starts_with(b"ID3"));
+        let tag_size = 10
+            + output[6..10]
+                .iter()
+                .fold(0usize, |size, v| (size << 7) | (*v as usize));
+        let (tag, body) = output.split_at(tag_size);
+        assert_eq!(body, plain_audio);
+        for needle in [
+            &b"TIT2"[..],
+            b"Parakeet",
+            b"TPE1",
+            b"Jixun",
+            b"TALB",
+            b"APIC",
+        ] {
+            assert!(tag.windows(needle.len()).any(|v| v == needle));
+        }
+        assert!(tag.ends_with(&cover));
+    }
+
+    #[test]
+    fn test_ncm_cover_frame_recovery() {
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let audio = generate_test_data(0x100, "ncm-test-data");
+        let expected = {
+            let mut decryptor = super::new_ncm(test_key[..].try_into().unwrap());
+            decryptor
+                .write(&make_ncm_file(&[0x01, 0x02, 0x03], &[], &audio))
+                .unwrap();
+            decryptor.read_all_output()
+        };
+
+        // Cover size of 4 bytes, larger than its frame of 2 bytes.
+        let mut test_data = make_ncm_file(&[0x01, 0x02, 0x03], &[], &audio);
+        let cover_offset = TEST_NCM_KEY_HEADER.len() + 4 + 3 + 5 + 8;
+        test_data[cover_offset - 4..cover_offset].copy_from_slice(&4u32.to_le_bytes());
+
+        let mut decryptor = super::new_ncm(test_key[..].try_into().unwrap());
+        let err = decryptor.write(&test_data).unwrap_err();
+        assert!(matches!(
+            err.code(),
+            DecryptErrorCode::NCMCoverFrameTooSmall
+        ));
+
+        let mut decryptor = super::new_ncm(test_key[..].try_into().unwrap());
+        decryptor.set_recovery_mode(true);
+        decryptor.write(&test_data).unwrap();
+        decryptor.end().unwrap();
+        assert_eq!(decryptor.read_all_output(), expected);
+
+        let damaged = decryptor.get_damaged_ranges();
+        assert_eq!(damaged.len(), 1);
+        assert_eq!((damaged[0].offset, damaged[0].len), (cover_offset, 2));
+    }
 }
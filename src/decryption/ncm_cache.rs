@@ -0,0 +1,59 @@
+mod detail {
+    /**
+     * @brief NetEase Cloud Music cache file (`.uc`)
+     *
+     * Every byte of the audio is XOR-ed with 0xA3; there is no header.
+     * See `netease::cache_info` for the `.idx`/`.info` sidecar files.
+     */
+    use crate::{
+        decryptor::{BaseDecryptorData, DecryptError, Decryptor},
+        impl_decryptor_inner_helper,
+    };
+
+    const NCM_CACHE_XOR_KEY: u8 = 0xA3;
+
+    struct NeteaseCacheDecryptor {
+        data: BaseDecryptorData,
+    }
+
+    impl NeteaseCacheDecryptor {
+        fn new() -> Self {
+            Self {
+                data: BaseDecryptorData::new("NeteaseCache"),
+            }
+        }
+    }
+
+    impl Decryptor for NeteaseCacheDecryptor {
+        impl_decryptor_inner_helper! {}
+
+        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            self.data
+                .buf_out
+                .extend(data.iter().map(|v| v ^ NCM_CACHE_XOR_KEY));
+            self.data.offset += data.len();
+            Ok(())
+        }
+    }
+
+    pub fn new_ncm_cache() -> impl Decryptor {
+        NeteaseCacheDecryptor::new()
+    }
+}
+
+pub use detail::new_ncm_cache;
+
+#[cfg(test)]
+mod test {
+    use crate::utils::test_util::test::{decrypt_test_content, generate_test_data, sha256};
+
+    #[test]
+    fn test_ncm_cache() {
+        let plain = generate_test_data(0x1000, "ncm-cache-data");
+        let encrypted: Vec<u8> = plain.iter().map(|v| v ^ 0xA3).collect();
+
+        let mut decryptor = super::new_ncm_cache();
+        let result = decrypt_test_content(&mut decryptor, encrypted);
+        assert_eq!(result, sha256(&plain));
+    }
+}
@@ -0,0 +1,111 @@
+mod detail {
+    use crate::{
+        decryptor::{DamagedRange, DecryptError, Decryptor},
+        utils::audio::{AudioType, AudioTypeSniffer},
+    };
+
+    /// Wraps a decryptor and detects the type of its output as it streams by.
+    ///
+    /// Output is passed through as soon as the inner decryptor produces it;
+    ///   only the few bytes needed for detection are copied aside.
+    pub struct OutputTypeDetector<D: Decryptor> {
+        inner: D,
+        sniffer: AudioTypeSniffer,
+        buf_out: Vec<u8>,
+    }
+
+    impl<D: Decryptor> OutputTypeDetector<D> {
+        /// `None` until enough plaintext was produced, or `end()` was called.
+        pub fn output_audio_type(&self) -> Option<AudioType> {
+            self.sniffer.audio_type()
+        }
+
+        pub fn into_inner(self) -> D {
+            self.inner
+        }
+
+        fn pull_output(&mut self) {
+            let mut out = self.inner.read_all_output();
+            self.sniffer.write(&out);
+            self.buf_out.append(&mut out);
+        }
+    }
+
+    impl<D: Decryptor> Decryptor for OutputTypeDetector<D> {
+        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            self.inner.write(data)?;
+            self.pull_output();
+            Ok(())
+        }
+
+        fn end(&mut self) -> Result<(), DecryptError> {
+            self.inner.end()?;
+            self.pull_output();
+            self.sniffer.end();
+            Ok(())
+        }
+
+        fn get_name(&self) -> &str {
+            self.inner.get_name()
+        }
+
+        fn read_all_output(&mut self) -> Vec<u8> {
+            std::mem::take(&mut self.buf_out)
+        }
+
+        fn get_eof_reserve(&self) -> usize {
+            self.inner.get_eof_reserve()
+        }
+
+        fn set_recovery_mode(&mut self, enabled: bool) {
+            self.inner.set_recovery_mode(enabled);
+        }
+
+        fn get_damaged_ranges(&self) -> &[DamagedRange] {
+            self.inner.get_damaged_ranges()
+        }
+    }
+
+    pub fn new_output_type_detector<D: Decryptor>(decryptor: D) -> OutputTypeDetector<D> {
+        OutputTypeDetector {
+            inner: decryptor,
+            sniffer: AudioTypeSniffer::new(),
+            buf_out: vec![],
+        }
+    }
+}
+
+pub use detail::new_output_type_detector;
+pub use detail::OutputTypeDetector;
+
+#[cfg(test)]
+mod test {
+    use super::new_output_type_detector;
+    use crate::{decryption::new_ncm_cache, decryptor::Decryptor, utils::audio::AudioType};
+
+    #[test]
+    fn test_output_type_detector() {
+        let mut plain = b"ID3\x03\x00\x00\x00\x00\x10\x00".to_vec();
+        plain.resize(plain.len() + 0x800, 0x55);
+        plain.extend_from_slice(b"OggS\x00\x02");
+        plain.resize(plain.len() + 0x100, 0);
+        let encrypted = plain.iter().map(|v| v ^ 0xA3).collect::<Vec<_>>();
+
+        let mut decryptor = new_output_type_detector(new_ncm_cache());
+        let mut output = vec![];
+        let mut written = 0;
+        for chunk in encrypted.chunks(0x100) {
+            decryptor.write(chunk).unwrap();
+            written += chunk.len();
+            // Output is never held back.
+            output.append(&mut decryptor.read_all_output());
+            assert_eq!(output, &plain[..written]);
+        }
+
+        assert_eq!(decryptor.output_audio_type(), Some(AudioType::OGG));
+        decryptor.end().unwrap();
+        output.append(&mut decryptor.read_all_output());
+        assert_eq!(output, plain);
+        assert_eq!(decryptor.get_name(), "NeteaseCache");
+    }
+}
@@ -1,7 +1,131 @@
 use super::{qmc_v1, qmc_v2};
-use crate::decryptor::Decryptor;
+use crate::{decryptor::Decryptor, utils::audio::AudioType};
 
-pub fn new_qmc_v2<T: AsRef<[u8]>>(footer: T) -> Option<Box<dyn Decryptor>> {
+/// Body cipher used by footer-keyed (QMCv2) files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QMCv2Cipher {
+    /// Map cipher, derived from a short (< 300 bytes) key.
+    Map,
+    /// Segmented RC4 cipher, used with longer keys.
+    RC4,
+}
+
+impl QMCv2Cipher {
+    /// Pick the cipher the same way the client does: by key length.
+    pub fn from_key_len(key_len: usize) -> Self {
+        if key_len < 300 {
+            QMCv2Cipher::Map
+        } else {
+            QMCv2Cipher::RC4
+        }
+    }
+}
+
+/// Where the decryption key of a QMC variant comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QMCKeySource {
+    /// Fixed key shared by every file, see `new_qmc_v1`.
+    Static,
+    /// Per-file ekey stored in the file footer, see `new_qmc_v2`.
+    Footer,
+}
+
+/// Declare [`QMCVariant`] from a single `variant => (ext, audio type, key source)` table,
+///   so the variant list and the per-variant properties cannot drift apart.
+macro_rules! define_qmc_variants {
+    ( $( $variant:ident => ($ext:literal, $audio_type:ident, $key_source:ident), )* ) => {
+        #[allow(clippy::upper_case_acronyms)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum QMCVariant {
+            $( $variant, )*
+        }
+
+        impl QMCVariant {
+            /// Every known variant.
+            pub const ALL: &'static [QMCVariant] = &[ $( QMCVariant::$variant, )* ];
+
+            pub fn to_ext(&self) -> &'static str {
+                match *self {
+                    $( QMCVariant::$variant => $ext, )*
+                }
+            }
+
+            /// Audio type expected after decryption.
+            pub fn audio_type(&self) -> AudioType {
+                match *self {
+                    $( QMCVariant::$variant => AudioType::$audio_type, )*
+                }
+            }
+
+            pub fn key_source(&self) -> QMCKeySource {
+                match *self {
+                    $( QMCVariant::$variant => QMCKeySource::$key_source, )*
+                }
+            }
+        }
+    };
+}
+
+// cspell:disable
+define_qmc_variants! {
+    QMC0 => ("qmc0", MP3, Static),
+    QMC2 => ("qmc2", OGG, Static),
+    QMC3 => ("qmc3", MP3, Static),
+    QMC4 => ("qmc4", OGG, Static),
+    QMC6 => ("qmc6", OGG, Static),
+    QMC8 => ("qmc8", OGG, Static),
+    QMCFLAC => ("qmcflac", FLAC, Static),
+    QMCOGG => ("qmcogg", OGG, Static),
+    TKM => ("tkm", M4A, Static),
+    BKCMP3 => ("bkcmp3", MP3, Static),
+    BKCM4A => ("bkcm4a", M4A, Static),
+    BKCFLAC => ("bkcflac", FLAC, Static),
+    BKCWAV => ("bkcwav", WAV, Static),
+    BKCAPE => ("bkcape", APE, Static),
+    BKCOGG => ("bkcogg", OGG, Static),
+    BKCWMA => ("bkcwma", WMA, Static),
+    HexFLAC => ("666c6163", FLAC, Static),
+    HexMP3 => ("6d7033", MP3, Static),
+    HexOGG => ("6f6767", OGG, Static),
+    HexM4A => ("6d3461", M4A, Static),
+    HexWAV => ("776176", WAV, Static),
+
+    MFLAC => ("mflac", FLAC, Footer),
+    MFLAC0 => ("mflac0", FLAC, Footer),
+    MFLACH => ("mflach", FLAC, Footer),
+    MGG => ("mgg", OGG, Footer),
+    MGG0 => ("mgg0", OGG, Footer),
+    MGG1 => ("mgg1", OGG, Footer),
+    MGGL => ("mggl", OGG, Footer),
+    MMP4 => ("mmp4", MP4, Footer),
+}
+// cspell:enable
+
+impl QMCVariant {
+    /// Look up a variant by its file extension (case-insensitive, leading dot optional).
+    pub fn from_ext(ext: &str) -> Option<Self> {
+        let ext = ext.strip_prefix('.').unwrap_or(ext);
+        QMCVariant::ALL
+            .iter()
+            .find(|v| v.to_ext().eq_ignore_ascii_case(ext))
+            .copied()
+    }
+}
+
+pub(crate) fn new_qmc_v2_from_key<T: AsRef<[u8]> + 'static>(
+    key: T,
+    eof_reserve: usize,
+    cipher: QMCv2Cipher,
+) -> Box<dyn Decryptor> {
+    match cipher {
+        QMCv2Cipher::Map => Box::new(qmc_v1::new_qmc_v1_map(key, eof_reserve)),
+        QMCv2Cipher::RC4 => Box::new(qmc_v2::new_qmc_v2_rc4(key, eof_reserve)),
+    }
+}
+
+/// Parse the footer and pick the cipher from the key length.
+/// Returns the cipher that was chosen alongside the decryptor.
+pub fn new_qmc_v2_auto<T: AsRef<[u8]>>(footer: T) -> Option<(QMCv2Cipher, Box<dyn Decryptor>)> {
     let parsed = crate::tencent::qmc_footer::parse(footer)?;
     let key_len = parsed.key.len();
 
@@ -9,15 +133,74 @@ pub fn new_qmc_v2<T: AsRef<[u8]>>(footer: T) -> Option<Box<dyn Decryptor>> {
         return None;
     }
 
-    if parsed.key.len() < 300 {
-        Some(Box::new(qmc_v1::new_qmc_v1_map(
-            parsed.key,
-            parsed.eof_bytes_ignore,
-        )))
-    } else {
-        Some(Box::new(qmc_v2::new_qmc_v2_rc4(
-            parsed.key,
-            parsed.eof_bytes_ignore,
-        )))
+    let cipher = QMCv2Cipher::from_key_len(key_len);
+    let decryptor = new_qmc_v2_from_key(parsed.key, parsed.eof_bytes_ignore, cipher);
+    Some((cipher, decryptor))
+}
+
+/// Parse the footer, then decrypt with the given cipher regardless of the key length.
+pub fn new_qmc_v2_with_cipher<T: AsRef<[u8]>>(
+    footer: T,
+    cipher: QMCv2Cipher,
+) -> Option<Box<dyn Decryptor>> {
+    let parsed = crate::tencent::qmc_footer::parse(footer)?;
+
+    if parsed.key.is_empty() {
+        return None;
+    }
+
+    Some(new_qmc_v2_from_key(
+        parsed.key,
+        parsed.eof_bytes_ignore,
+        cipher,
+    ))
+}
+
+pub fn new_qmc_v2<T: AsRef<[u8]>>(footer: T) -> Option<Box<dyn Decryptor>> {
+    new_qmc_v2_auto(footer).map(|(_, decryptor)| decryptor)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{QMCKeySource, QMCVariant, QMCv2Cipher};
+    use crate::utils::{
+        audio::{AudioExtensionName, AudioType},
+        test_util::test::TEST_QMC_V2_RC4_EKEY,
+    };
+
+    fn make_pc_footer(ekey: &str) -> Vec<u8> {
+        let mut footer = Vec::from(ekey.as_bytes());
+        footer.extend_from_slice(&(ekey.len() as u32).to_le_bytes());
+        footer
+    }
+
+    #[test]
+    fn test_qmc_variant_from_ext() {
+        let mflac = QMCVariant::from_ext(".MFLAC0").unwrap();
+        assert_eq!(mflac, QMCVariant::MFLAC0);
+        assert_eq!(mflac.key_source(), QMCKeySource::Footer);
+        assert_eq!(mflac.audio_type().to_audio_ext(), "flac");
+
+        let hex_flac = QMCVariant::from_ext("666c6163").unwrap();
+        assert_eq!(hex_flac.key_source(), QMCKeySource::Static);
+        assert_eq!(hex_flac.audio_type(), AudioType::FLAC);
+
+        assert_eq!(QMCVariant::from_ext("mp3"), None);
+
+        for variant in QMCVariant::ALL {
+            assert_eq!(QMCVariant::from_ext(variant.to_ext()), Some(*variant));
+        }
+    }
+
+    #[test]
+    fn test_qmc_v2_auto_cipher() {
+        let footer = make_pc_footer(TEST_QMC_V2_RC4_EKEY);
+
+        let (cipher, _) = super::new_qmc_v2_auto(&footer).unwrap();
+        assert_eq!(cipher, QMCv2Cipher::RC4);
+        assert!(super::new_qmc_v2_with_cipher(&footer, QMCv2Cipher::Map).is_some());
+
+        assert_eq!(QMCv2Cipher::from_key_len(299), QMCv2Cipher::Map);
+        assert_eq!(QMCv2Cipher::from_key_len(300), QMCv2Cipher::RC4);
     }
 }
@@ -79,7 +79,7 @@ mod detail {
             let new_offset = offset + n;
 
             let buf = self.data.buf_out.append_data(data);
-            buf.xor_key_with_key_offset(&self.cache, offset);
+            buf.xor_key_with_key_offset(self.cache, offset);
 
             if offset <= STATIC_CIPHER_PAGE_SIZE && STATIC_CIPHER_PAGE_SIZE < new_offset {
                 buf[STATIC_CIPHER_PAGE_SIZE - offset] ^= self.extra_cache_value;
@@ -0,0 +1,203 @@
+use crate::utils::audio::AudioType;
+
+/// Size of the header preceding the audio data.
+pub const XIAMI_HEADER_SIZE: usize = 0x10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XiamiHeader {
+    /// Declared type of the inner audio.
+    pub audio_type: AudioType,
+    /// Number of plain bytes at the start of the audio data.
+    pub encrypted_offset: usize,
+    pub key: u8,
+}
+
+mod detail {
+    /**
+     * @brief Xiami `ifmt` format
+     *
+     *   0000h: "ifmt"
+     *   0004h: char[4] audio type, " MP3", "FLAC", " A4M" or " WAV"
+     *   0008h: FE FE FE FE
+     *   000Ch: u24_le(encrypted_offset) u8(key)
+     *   0010h: audio data
+     *
+     * Audio data before `encrypted_offset` is plain,
+     *   each byte after it is decrypted as `!(v - key)`.
+     */
+    use super::{XiamiHeader, XIAMI_HEADER_SIZE};
+    use crate::{
+        decryptor::{BaseDecryptorData, DecryptError, DecryptErrorCode, Decryptor},
+        impl_decryptor_inner_helper,
+        utils::{array_ext::ByteSliceExt, audio::AudioType},
+    };
+
+    const XIAMI_MAGIC: &[u8; 4] = b"ifmt";
+    const XIAMI_MAGIC_2: &[u8; 4] = &[0xFE, 0xFE, 0xFE, 0xFE];
+
+    pub fn parse_header(buf: &[u8]) -> Result<XiamiHeader, DecryptError> {
+        if buf.len() < XIAMI_HEADER_SIZE {
+            return Err(DecryptError::new(
+                DecryptErrorCode::InvalidBlockSize,
+                "xiami header too small",
+            ));
+        }
+
+        if &buf[0..4] != XIAMI_MAGIC || &buf[8..12] != XIAMI_MAGIC_2 {
+            return Err(DecryptError::new(
+                DecryptErrorCode::UnknownMagicHeader,
+                "unknown magic header",
+            ));
+        }
+
+        let audio_type = match &buf[4..8] {
+            b" MP3" => AudioType::MP3,
+            b"FLAC" => AudioType::FLAC,
+            b" A4M" => AudioType::M4A,
+            b" WAV" => AudioType::WAV,
+            _ => AudioType::UnknownType,
+        };
+
+        Ok(XiamiHeader {
+            audio_type,
+            encrypted_offset: (buf.read_le::<u32>(12) & 0x00FF_FFFF) as usize,
+            key: buf[15],
+        })
+    }
+
+    pub struct XiamiDecryptor {
+        data: BaseDecryptorData,
+        /// `None` while reading the header.
+        header: Option<XiamiHeader>,
+    }
+
+    impl XiamiDecryptor {
+        fn new() -> Self {
+            Self {
+                data: BaseDecryptorData::new("Xiami"),
+                header: None,
+            }
+        }
+
+        /// `None` until the header was read.
+        pub fn header(&self) -> Option<&XiamiHeader> {
+            self.header.as_ref()
+        }
+    }
+
+    impl Decryptor for XiamiDecryptor {
+        impl_decryptor_inner_helper! {}
+
+        fn end(&mut self) -> Result<(), DecryptError> {
+            match self.header {
+                None => Err(DecryptError::new(
+                    DecryptErrorCode::InvalidBlockSize,
+                    "xiami header too small",
+                )),
+                Some(_) => Ok(()),
+            }
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            let mut p = data;
+
+            while !p.is_empty() {
+                match &self.header {
+                    None => {
+                        if self.data.read_until_offset(&mut p, XIAMI_HEADER_SIZE) {
+                            self.header = Some(parse_header(&self.data.buf_in)?);
+                            self.data.seek_input(XIAMI_HEADER_SIZE);
+                        }
+                    }
+
+                    Some(header) => {
+                        let encrypted_offset = XIAMI_HEADER_SIZE + header.encrypted_offset;
+                        let key = header.key;
+
+                        let plain_len =
+                            usize::min(encrypted_offset.saturating_sub(self.data.offset), p.len());
+                        let (plain, encrypted) = p.split_at(plain_len);
+                        self.data.buf_out.extend_from_slice(plain);
+                        self.data
+                            .buf_out
+                            .extend(encrypted.iter().map(|v| !v.wrapping_sub(key)));
+                        self.data.offset += p.len();
+                        break;
+                    }
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    pub fn new_xiami() -> XiamiDecryptor {
+        XiamiDecryptor::new()
+    }
+}
+
+pub use detail::new_xiami;
+pub use detail::parse_header;
+pub use detail::XiamiDecryptor;
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::{
+            audio::AudioType,
+            test_util::test::{decrypt_test_content, generate_test_data, sha256},
+        },
+    };
+
+    #[test]
+    fn test_xiami() {
+        let plain = generate_test_data(0x1000, "xiami-test-data");
+        let key = 0x5Au8;
+        let encrypted_offset = 0x123usize;
+
+        let mut test_data = Vec::from(&b"ifmtFLAC\xFE\xFE\xFE\xFE"[..]);
+        test_data.extend_from_slice(&(encrypted_offset as u32).to_le_bytes()[..3]);
+        test_data.push(key);
+
+        let header = super::parse_header(&test_data).unwrap();
+        assert_eq!(header.audio_type, AudioType::FLAC);
+        assert_eq!(header.encrypted_offset, encrypted_offset);
+        assert_eq!(header.key, key);
+
+        test_data.extend_from_slice(&plain[..encrypted_offset]);
+        test_data.extend(
+            plain[encrypted_offset..]
+                .iter()
+                .map(|v| (!v).wrapping_add(key)),
+        );
+
+        let mut decryptor = super::new_xiami();
+        let result = decrypt_test_content(&mut decryptor, test_data);
+        assert_eq!(result, sha256(&plain));
+
+        assert!(super::parse_header(b"ifmt MP3\x00\x00\x00\x00\x00\x00\x00\x00").is_err());
+    }
+
+    #[test]
+    fn test_xiami_header() {
+        let test_data = b"ifmt A4M\xFE\xFE\xFE\xFE\x10\x00\x00\x5A";
+
+        let mut decryptor = super::new_xiami();
+        decryptor.write(&test_data[..12]).unwrap();
+        assert!(decryptor.header().is_none());
+        decryptor.write(&test_data[12..]).unwrap();
+        let header = decryptor.header().unwrap();
+        assert_eq!(header.audio_type, AudioType::M4A);
+        assert_eq!((header.encrypted_offset, header.key), (0x10, 0x5A));
+        decryptor.end().unwrap();
+
+        // Truncated header
+        let mut decryptor = super::new_xiami();
+        decryptor.write(&test_data[..12]).unwrap();
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidBlockSize));
+        assert!(decryptor.header().is_none());
+        assert!(decryptor.read_all_output().is_empty());
+    }
+}
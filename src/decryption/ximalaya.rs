@@ -1,10 +1,88 @@
+use crate::decryptor::{DecryptError, DecryptErrorCode};
+
 pub const XMLY_SCRAMBLE_SIZE: usize = 1024;
 pub const X2M_CONTENT_KEY_SIZE: usize = 0x04;
 pub const X3M_CONTENT_KEY_SIZE: usize = 0x20;
 
 pub type X2MContentKey = [u8; X2M_CONTENT_KEY_SIZE];
 pub type X3MContentKey = [u8; X3M_CONTENT_KEY_SIZE];
-pub type ScrambleTable = [u16; XMLY_SCRAMBLE_SIZE];
+
+/// A permutation of `0..XMLY_SCRAMBLE_SIZE`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrambleTable([u16; XMLY_SCRAMBLE_SIZE]);
+
+impl ScrambleTable {
+    /// Fails with `InvalidScrambleTable` if `table` is not a permutation of `0..XMLY_SCRAMBLE_SIZE`.
+    pub fn new(table: [u16; XMLY_SCRAMBLE_SIZE]) -> Result<Self, DecryptError> {
+        let mut seen = [false; XMLY_SCRAMBLE_SIZE];
+        for (i, &idx) in table.iter().enumerate() {
+            match seen.get_mut(idx as usize) {
+                Some(seen) if !*seen => *seen = true,
+                _ => {
+                    return Err(DecryptError::new(
+                        DecryptErrorCode::InvalidScrambleTable,
+                        &format!("invalid scramble table entry {} at {}", idx, i),
+                    ))
+                }
+            }
+        }
+
+        Ok(Self(table))
+    }
+
+    /// Generate a scramble table from seed parameters,
+    ///   using the logistic map `x' = step * x * (1 - x)`, starting from `init`.
+    ///
+    /// Not yet verified against a table dumped from the client; prefer `new`
+    ///   with a dumped table until it is. Unverified choices:
+    ///   argsort (not its inverse), `init` as the first value, and ties kept in sequence order.
+    /// The last one matters: in `f64`, the sequence can settle into an exact cycle
+    ///   (e.g. for `step = 3.837465`), so most entries are decided by the tie-break.
+    pub fn from_params(init: f64, step: f64) -> Self {
+        // Output byte `i` of the header is read from input byte `table[i]`.
+        let mut sequence = [(0f64, 0u16); XMLY_SCRAMBLE_SIZE];
+        let mut value = init;
+        for (i, item) in sequence.iter_mut().enumerate() {
+            *item = (value, i as u16);
+            value = step * value * (1.0 - value);
+        }
+        sequence.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut table = [0u16; XMLY_SCRAMBLE_SIZE];
+        for (v, (_, idx)) in table.iter_mut().zip(sequence.iter()) {
+            *v = *idx;
+        }
+        Self(table)
+    }
+
+    pub fn as_array(&self) -> &[u16; XMLY_SCRAMBLE_SIZE] {
+        &self.0
+    }
+}
+
+impl TryFrom<[u16; XMLY_SCRAMBLE_SIZE]> for ScrambleTable {
+    type Error = DecryptError;
+
+    fn try_from(table: [u16; XMLY_SCRAMBLE_SIZE]) -> Result<Self, Self::Error> {
+        Self::new(table)
+    }
+}
+
+// AES-256
+pub const XM_KEY_SIZE: usize = 256 / 8;
+pub type XMKey = [u8; XM_KEY_SIZE];
+
+/// Fields of the ID3v2 tag wrapping a PC client `.xm` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XMHeader {
+    /// Size of the ID3v2 tag, including its header.
+    pub tag_size: usize,
+    /// Size of the encrypted section following the tag.
+    pub encrypted_size: usize,
+    pub iv: [u8; 16],
+    /// `0` if not present.
+    pub track_id: u64,
+}
 
 mod detail {
     use super::{ScrambleTable, X2MContentKey, X3MContentKey, XMLY_SCRAMBLE_SIZE};
@@ -38,13 +116,13 @@ mod detail {
         }
 
         fn do_header_decryption(&mut self) {
+            // Entries are always within the header, see `ScrambleTable::new`.
             let mut output = self
                 .scramble_table
+                .as_array()
                 .iter()
                 .enumerate()
-                .map(|(i, idx)| -> u8 {
-                    self.data.buf_in.get_value_unchecked(*idx) ^ self.key.get_mod_n(i)
-                })
+                .map(|(i, &idx)| -> u8 { self.data.buf_in[idx as usize] ^ self.key.get_mod_n(i) })
                 .collect();
             self.data.buf_out.append(&mut output);
             self.data.seek_input(XMLY_SCRAMBLE_SIZE);
@@ -86,14 +164,207 @@ mod detail {
     }
 }
 
+mod detail_xm {
+    /**
+     * @brief Ximalaya PC client `.xm` format
+     *
+     *   id3v2_tag   := ID3v2 tag, with text frames
+     *                    TSIZ: size of the encrypted section
+     *                    TSRC: hex IV (or TENC, if TSRC is empty)
+     *                    TRCK: track id
+     *   encrypted   := AES-256-CBC(key, iv, base64(audio_header))  // PKCS#7 padded
+     *   audio_body  := plain audio
+     *
+     * Output is `audio_header` followed by `audio_body`; the tag is dropped.
+     */
+    use super::{XMHeader, XMKey};
+    use crate::{
+        decryptor::{BaseDecryptorData, DecryptError, DecryptErrorCode, Decryptor},
+        impl_decryptor_inner_helper,
+        utils::audio::{find_id3v2_text_frame, get_id3v2_size},
+    };
+    use aes::Aes256;
+    use cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+
+    const ID3V2_HEADER_SIZE: usize = 10;
+    const XM_IV_SIZE: usize = 16;
+    const AES_BLOCK_SIZE: usize = 16;
+
+    /// The encrypted section only holds the (base64 encoded) audio header,
+    ///   anything larger is a corrupted or hostile TSIZ.
+    const XM_MAX_ENCRYPTED_SIZE: usize = 16 * 1024 * 1024;
+
+    #[inline]
+    fn invalid_header(reason: &str) -> DecryptError {
+        DecryptError::new(DecryptErrorCode::XMInvalidHeader, reason)
+    }
+
+    /// Size of the ID3v2 tag (header, frames and footer) from its 10 byte header.
+    fn parse_id3_tag_size(header: &[u8]) -> Result<usize, DecryptError> {
+        let tag_size = get_id3v2_size(header, b"ID3").ok_or_else(|| {
+            DecryptError::new(DecryptErrorCode::UnknownMagicHeader, "unknown magic header")
+        })?;
+
+        // Sync-safe integers never have the high bit set.
+        if header[6..ID3V2_HEADER_SIZE].iter().any(|&b| b & 0x80 != 0) {
+            return Err(invalid_header("invalid id3 tag size"));
+        }
+        if tag_size <= ID3V2_HEADER_SIZE {
+            return Err(invalid_header("empty id3 tag"));
+        }
+
+        Ok(tag_size)
+    }
+
+    /// `buf` must hold the complete ID3v2 tag.
+    pub fn parse_xm_header(buf: &[u8]) -> Result<XMHeader, DecryptError> {
+        let tag_size = parse_id3_tag_size(buf)?;
+        if buf.len() < tag_size {
+            return Err(DecryptError::new(
+                DecryptErrorCode::InvalidBlockSize,
+                "incomplete id3 tag",
+            ));
+        }
+
+        let tag = &buf[..tag_size];
+        let encrypted_size = find_id3v2_text_frame(tag, "TSIZ")
+            .and_then(|size| size.trim().parse::<usize>().ok())
+            .ok_or_else(|| invalid_header("missing encrypted size (TSIZ)"))?;
+        if encrypted_size == 0
+            || encrypted_size > XM_MAX_ENCRYPTED_SIZE
+            || encrypted_size % AES_BLOCK_SIZE != 0
+        {
+            return Err(invalid_header("invalid encrypted size (TSIZ)"));
+        }
+
+        let iv = find_id3v2_text_frame(tag, "TSRC")
+            .filter(|iv| !iv.is_empty())
+            .or_else(|| find_id3v2_text_frame(tag, "TENC"))
+            .and_then(|iv| {
+                data_encoding::HEXLOWER_PERMISSIVE
+                    .decode(iv.trim().as_bytes())
+                    .ok()
+            })
+            .and_then(|iv| <[u8; XM_IV_SIZE]>::try_from(iv).ok())
+            .ok_or_else(|| invalid_header("missing or invalid iv (TSRC/TENC)"))?;
+
+        let track_id = find_id3v2_text_frame(tag, "TRCK")
+            .and_then(|track| track.split('/').next().and_then(|v| v.trim().parse().ok()))
+            .unwrap_or_default();
+
+        Ok(XMHeader {
+            tag_size,
+            encrypted_size,
+            iv,
+            track_id,
+        })
+    }
+
+    enum State {
+        ReadTagHeader,
+        ReadTag(usize),
+        DecryptHeader(XMHeader),
+        PassThrough,
+    }
+
+    struct XimalayaPC {
+        data: BaseDecryptorData,
+        state: State,
+        key: XMKey,
+    }
+
+    impl XimalayaPC {
+        fn new(key: &XMKey) -> Self {
+            Self {
+                data: BaseDecryptorData::new("Ximalaya(XM)"),
+                state: State::ReadTagHeader,
+                key: *key,
+            }
+        }
+
+        fn decrypt_header(
+            &mut self,
+            encrypted_size: usize,
+            iv: [u8; XM_IV_SIZE],
+        ) -> Result<(), DecryptError> {
+            let mut encrypted = self.data.consume_input(encrypted_size);
+            let decrypted = cbc::Decryptor::<Aes256>::new(&self.key.into(), &iv.into())
+                .decrypt_padded_mut::<Pkcs7>(&mut encrypted)
+                .map_err(|_| {
+                    DecryptError::new(DecryptErrorCode::AESParamError, "decrypt xm header failed")
+                })?;
+
+            let decrypted = String::from_utf8_lossy(decrypted);
+            let mut audio_header = base64::decode(decrypted.trim_end_matches(['\0', '\r', '\n']))
+                .map_err(|_| invalid_header("invalid base64 data"))?;
+            self.data.buf_out.append(&mut audio_header);
+            Ok(())
+        }
+    }
+
+    impl Decryptor for XimalayaPC {
+        impl_decryptor_inner_helper! {}
+
+        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            let mut p = data;
+
+            while !p.is_empty() {
+                match self.state {
+                    State::ReadTagHeader => {
+                        if self.data.read_until_offset(&mut p, ID3V2_HEADER_SIZE) {
+                            let tag_size = parse_id3_tag_size(&self.data.buf_in)?;
+                            self.state = State::ReadTag(tag_size);
+                        }
+                    }
+
+                    State::ReadTag(n) => {
+                        if self.data.read_until_offset(&mut p, n) {
+                            let header = parse_xm_header(&self.data.buf_in)?;
+                            self.data.seek_input(n);
+                            self.state = State::DecryptHeader(header);
+                        }
+                    }
+
+                    State::DecryptHeader(ref header) => {
+                        let (encrypted_size, iv) = (header.encrypted_size, header.iv);
+                        if self.data.read_block(&mut p, encrypted_size) {
+                            self.data.offset += encrypted_size;
+                            self.decrypt_header(encrypted_size, iv)?;
+                            self.state = State::PassThrough;
+                        }
+                    }
+
+                    State::PassThrough => {
+                        self.data.buf_out.extend_from_slice(p);
+                        self.data.offset += p.len();
+                        break;
+                    }
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    pub fn new_xm(key: &XMKey) -> impl Decryptor {
+        XimalayaPC::new(key)
+    }
+}
+
 pub use detail::new_x2m;
 pub use detail::new_x3m;
+pub use detail_xm::new_xm;
+pub use detail_xm::parse_xm_header;
 
 #[cfg(test)]
 pub mod test {
-    use super::{ScrambleTable, XMLY_SCRAMBLE_SIZE};
+    use super::{ScrambleTable, XMKey, XMLY_SCRAMBLE_SIZE, XM_KEY_SIZE};
+    use aes::Aes256;
+    use cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+
     use crate::{
         decryption::ximalaya::{X2M_CONTENT_KEY_SIZE, X3M_CONTENT_KEY_SIZE},
+        decryptor::{DecryptErrorCode, Decryptor},
         utils::array_ext::ByteSliceExt,
         utils::test_util::test::{decrypt_test_content, generate_test_data, TEST_SIZE_1MB},
     };
@@ -103,7 +374,7 @@ pub mod test {
         let test_data = generate_test_data(TEST_SIZE_1MB, "x2m-test-data");
         let x2m_content_key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m content key");
 
-        let mut x2m_scramble_table: ScrambleTable = [0u16; XMLY_SCRAMBLE_SIZE];
+        let mut x2m_scramble_table = [0u16; XMLY_SCRAMBLE_SIZE];
         for (i, v) in x2m_scramble_table.iter_mut().enumerate() {
             *v = i as u16;
         }
@@ -120,7 +391,7 @@ pub mod test {
             x2m_content_key
                 .try_into()
                 .expect("could not format to array"),
-            x2m_scramble_table,
+            ScrambleTable::new(x2m_scramble_table).unwrap(),
         );
         let result = decrypt_test_content(&mut decryptor, test_data);
         assert_eq!(
@@ -134,7 +405,7 @@ pub mod test {
         let test_data = generate_test_data(TEST_SIZE_1MB, "x3m-test-data");
         let x3m_content_key = generate_test_data(X3M_CONTENT_KEY_SIZE, "x3m content key");
 
-        let mut x3m_scramble_table: ScrambleTable = [0u16; XMLY_SCRAMBLE_SIZE];
+        let mut x3m_scramble_table = [0u16; XMLY_SCRAMBLE_SIZE];
         for (i, v) in x3m_scramble_table.iter_mut().enumerate() {
             *v = i as u16;
         }
@@ -151,7 +422,7 @@ pub mod test {
             x3m_content_key
                 .try_into()
                 .expect("could not format to array"),
-            x3m_scramble_table,
+            ScrambleTable::new(x3m_scramble_table).unwrap(),
         );
         let result = decrypt_test_content(&mut decryptor, test_data);
         assert_eq!(
@@ -159,4 +430,129 @@ pub mod test {
             "a10bbfdcdbd388373361da6baf35c80b725f7310c3eca29d7dcf228e397a8c5a"
         );
     }
+
+    fn make_id3_text_frame(id: &[u8; 4], text: &str) -> Vec<u8> {
+        let payload = [&[0u8], text.as_bytes()].concat();
+        [
+            id,
+            &(payload.len() as u32).to_be_bytes()[..],
+            &[0, 0],
+            &payload,
+        ]
+        .concat()
+    }
+
+    fn make_id3_tag(frames: &[u8]) -> Vec<u8> {
+        let size = frames.len() as u32;
+        [
+            &b"ID3\x03\x00\x00"[..],
+            &[
+                (size >> 21) as u8 & 0x7F,
+                (size >> 14) as u8 & 0x7F,
+                (size >> 7) as u8 & 0x7F,
+                size as u8 & 0x7F,
+            ],
+            frames,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_xm() {
+        let key: XMKey = generate_test_data(XM_KEY_SIZE, "xm key")
+            .try_into()
+            .unwrap();
+        let iv: [u8; 16] = generate_test_data(16, "xm iv").try_into().unwrap();
+        let audio_header = generate_test_data(0x321, "xm audio header");
+        let audio_body = generate_test_data(0x1000, "xm audio body");
+
+        let encoded = base64::encode(&audio_header);
+        let mut encrypted = vec![0u8; (encoded.len() / 16 + 1) * 16];
+        let encrypted = cbc::Encryptor::<Aes256>::new(&key.into(), &iv.into())
+            .encrypt_padded_b2b_mut::<Pkcs7>(encoded.as_bytes(), &mut encrypted)
+            .unwrap()
+            .to_vec();
+
+        let frames = [
+            make_id3_text_frame(b"TIT2", "Parakeet"),
+            make_id3_text_frame(b"TRCK", "42"),
+            make_id3_text_frame(b"TSIZ", &encrypted.len().to_string()),
+            make_id3_text_frame(b"TSRC", &data_encoding::HEXLOWER.encode(&iv)),
+        ]
+        .concat();
+        let mut test_data = make_id3_tag(&frames);
+
+        let header = super::parse_xm_header(&test_data).unwrap();
+        assert_eq!(header.tag_size, test_data.len());
+        assert_eq!(header.encrypted_size, encrypted.len());
+        assert_eq!(header.iv, iv);
+        assert_eq!(header.track_id, 42);
+
+        test_data.extend_from_slice(&encrypted);
+        test_data.extend_from_slice(&audio_body);
+
+        let mut decryptor = super::new_xm(&key);
+        for chunk in test_data.chunks(0x100) {
+            decryptor.write(chunk).unwrap();
+        }
+        decryptor.end().unwrap();
+        assert_eq!(
+            decryptor.read_all_output(),
+            [audio_header, audio_body].concat()
+        );
+    }
+
+    #[test]
+    fn test_xm_invalid_header() {
+        let key = [0u8; XM_KEY_SIZE];
+        let iv = data_encoding::HEXLOWER.encode(&[0u8; 16]);
+        let tag_with_size = |size: &str| {
+            make_id3_tag(
+                &[
+                    make_id3_text_frame(b"TSIZ", size),
+                    make_id3_text_frame(b"TSRC", &iv),
+                ]
+                .concat(),
+            )
+        };
+
+        let invalid_tags = [
+            // Empty tag
+            b"ID3\x03\x00\x00\x00\x00\x00\x00".to_vec(),
+            // Size is not sync-safe
+            b"ID3\x03\x00\x00\x00\x00\x00\x80".to_vec(),
+            tag_with_size("0"),
+            tag_with_size("100"),
+            tag_with_size(&(1u64 << 32).to_string()),
+        ];
+        for tag in invalid_tags {
+            let err = super::parse_xm_header(&tag).unwrap_err();
+            assert!(matches!(err.code(), DecryptErrorCode::XMInvalidHeader));
+
+            let mut decryptor = super::new_xm(&key);
+            let err = decryptor.write(&tag).unwrap_err();
+            assert!(matches!(err.code(), DecryptErrorCode::XMInvalidHeader));
+        }
+    }
+
+    #[test]
+    fn test_scramble_table_from_params() {
+        for (init, step) in [(0.615243, 3.837465), (0.726354, 3.948576)] {
+            let table = ScrambleTable::from_params(init, step);
+            assert!(ScrambleTable::new(*table.as_array()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_scramble_table_validation() {
+        let mut table = *ScrambleTable::from_params(0.615243, 3.837465).as_array();
+        assert!(ScrambleTable::new(table).is_ok());
+
+        table[7] = XMLY_SCRAMBLE_SIZE as u16;
+        let err = ScrambleTable::new(table).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidScrambleTable));
+
+        table[7] = table[8];
+        assert!(ScrambleTable::try_from(table).is_err());
+    }
 }
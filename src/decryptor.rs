@@ -5,9 +5,11 @@ pub struct BaseDecryptorData {
     pub(crate) buf_in: Vec<u8>,
     pub(crate) buf_out: Vec<u8>,
     pub(crate) reserve_eof: usize,
+    pub(crate) recovery_mode: bool,
+    pub(crate) damaged_ranges: Vec<DamagedRange>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum DecryptErrorCode {
     UnknownEncryption,
     UnknownMagicHeader,
@@ -15,15 +17,34 @@ pub enum DecryptErrorCode {
     AESParamError,
     NCMInvalidContentKey,
     NCMCoverFrameTooSmall,
+    NCMInvalidMetadata,
+    UnsupportedVersion,
+    KeyNotFound,
+    InvalidEKey,
+    OutputSizeMismatch,
+    XMInvalidHeader,
+    InvalidScrambleTable,
+    DecompressError,
+    InvalidLyrics,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct DecryptError {
     code: DecryptErrorCode,
     str: String,
 }
 
+/// Input that could not be decrypted in recovery mode;
+///   a best guess was emitted in its place.
+#[derive(Debug, Clone)]
+pub struct DamagedRange {
+    /// Offset in the input.
+    pub offset: usize,
+    pub len: usize,
+    pub error: DecryptError,
+}
+
 impl DecryptError {
     pub fn new(code: DecryptErrorCode, str: &str) -> DecryptError {
         DecryptError {
@@ -31,6 +52,10 @@ impl DecryptError {
             str: str.into(),
         }
     }
+
+    pub fn code(&self) -> &DecryptErrorCode {
+        &self.code
+    }
 }
 
 impl BaseDecryptorData {
@@ -42,6 +67,8 @@ impl BaseDecryptorData {
             offset: 0,
             name: String::from(name),
             reserve_eof: 0,
+            recovery_mode: false,
+            damaged_ranges: vec![],
         }
     }
 
@@ -53,6 +80,8 @@ impl BaseDecryptorData {
             offset: 0,
             name: String::from(name),
             reserve_eof: reserve_len,
+            recovery_mode: false,
+            damaged_ranges: vec![],
         }
     }
 
@@ -101,6 +130,24 @@ impl BaseDecryptorData {
         result
     }
 
+    /// In recovery mode, record the damaged range and continue;
+    ///   otherwise fail with `error`.
+    #[inline]
+    pub(crate) fn recover_or_fail(
+        &mut self,
+        offset: usize,
+        len: usize,
+        error: DecryptError,
+    ) -> Result<(), DecryptError> {
+        if !self.recovery_mode {
+            return Err(error);
+        }
+
+        self.damaged_ranges
+            .push(DamagedRange { offset, len, error });
+        Ok(())
+    }
+
     #[inline(always)]
     pub(crate) fn read_all_output(&mut self) -> Vec<u8> {
         let mut result: Vec<u8> = vec![];
@@ -109,6 +156,62 @@ impl BaseDecryptorData {
     }
 }
 
+/// Decryptor for the body of a container, driven by the container's decryptor.
+///
+/// Output and damaged ranges are moved into the outer `BaseDecryptorData`,
+///   with offsets made relative to the outer input.
+pub(crate) struct BodyDecryptor {
+    inner: Box<dyn Decryptor>,
+    /// Offset of the body in the outer input.
+    body_offset: usize,
+    /// Number of the inner damaged ranges already moved out.
+    ranges_seen: usize,
+}
+
+impl BodyDecryptor {
+    pub(crate) fn new(
+        mut inner: Box<dyn Decryptor>,
+        body_offset: usize,
+        recovery_mode: bool,
+    ) -> Self {
+        inner.set_recovery_mode(recovery_mode);
+        BodyDecryptor {
+            inner,
+            body_offset,
+            ranges_seen: 0,
+        }
+    }
+
+    pub(crate) fn write(
+        &mut self,
+        outer: &mut BaseDecryptorData,
+        data: &[u8],
+    ) -> Result<(), DecryptError> {
+        self.inner.write(data)?;
+        self.pull(outer);
+        Ok(())
+    }
+
+    pub(crate) fn end(&mut self, outer: &mut BaseDecryptorData) -> Result<(), DecryptError> {
+        self.inner.end()?;
+        self.pull(outer);
+        Ok(())
+    }
+
+    fn pull(&mut self, outer: &mut BaseDecryptorData) {
+        outer.buf_out.append(&mut self.inner.read_all_output());
+
+        let ranges = &self.inner.get_damaged_ranges()[self.ranges_seen..];
+        outer
+            .damaged_ranges
+            .extend(ranges.iter().map(|range| DamagedRange {
+                offset: self.body_offset + range.offset,
+                ..range.clone()
+            }));
+        self.ranges_seen += ranges.len();
+    }
+}
+
 pub trait Decryptor {
     fn write(&mut self, data: &[u8]) -> Result<(), DecryptError>;
     #[inline(always)]
@@ -119,6 +222,18 @@ pub trait Decryptor {
     fn get_name(&self) -> &str;
     fn read_all_output(&mut self) -> Vec<u8>;
     fn get_eof_reserve(&self) -> usize;
+
+    /// Opt-in: continue past recoverable errors instead of failing,
+    ///   see `get_damaged_ranges`.
+    ///
+    /// Decryptors without recoverable errors may ignore it.
+    #[inline(always)]
+    fn set_recovery_mode(&mut self, _enabled: bool) {}
+    /// Ranges recovered so far; complete after `end()`.
+    #[inline(always)]
+    fn get_damaged_ranges(&self) -> &[DamagedRange] {
+        &[]
+    }
 }
 
 mod detail {
@@ -139,6 +254,66 @@ mod detail {
             fn get_eof_reserve(&self) -> usize {
                 self.data.reserve_eof
             }
+
+            #[inline(always)]
+            fn set_recovery_mode(&mut self, enabled: bool) {
+                self.data.recovery_mode = enabled;
+            }
+
+            #[inline(always)]
+            fn get_damaged_ranges(&self) -> &[$crate::decryptor::DamagedRange] {
+                &self.data.damaged_ranges
+            }
         };
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{BaseDecryptorData, BodyDecryptor, DecryptError, DecryptErrorCode, Decryptor};
+    use crate::impl_decryptor_inner_helper;
+
+    /// Passes input through, marking every write as damaged.
+    struct DamageEveryWrite {
+        data: BaseDecryptorData,
+    }
+
+    impl Decryptor for DamageEveryWrite {
+        impl_decryptor_inner_helper! {}
+
+        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            let error = DecryptError::new(DecryptErrorCode::InvalidBlockSize, "damaged");
+            self.data
+                .recover_or_fail(self.data.offset, data.len(), error)?;
+            self.data.offset += data.len();
+            self.data.buf_out.extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    fn new_body(recovery_mode: bool) -> BodyDecryptor {
+        let inner = DamageEveryWrite {
+            data: BaseDecryptorData::new("test"),
+        };
+        BodyDecryptor::new(Box::new(inner), 0x10, recovery_mode)
+    }
+
+    #[test]
+    fn test_body_decryptor_recovery() {
+        let mut outer = BaseDecryptorData::new("outer");
+        assert!(new_body(false).write(&mut outer, b"abc").is_err());
+
+        let mut body = new_body(true);
+        body.write(&mut outer, b"abc").unwrap();
+        body.write(&mut outer, b"de").unwrap();
+        body.end(&mut outer).unwrap();
+
+        assert_eq!(outer.read_all_output(), b"abcde");
+        let ranges: Vec<_> = outer
+            .damaged_ranges
+            .iter()
+            .map(|range| (range.offset, range.len))
+            .collect();
+        assert_eq!(ranges, [(0x10, 3), (0x13, 2)]);
+    }
+}
@@ -1,5 +1,7 @@
 pub mod decryption;
 pub mod decryptor;
+pub mod lyrics;
+pub mod netease;
 pub mod tencent;
 pub mod utils;
 
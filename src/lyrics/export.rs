@@ -0,0 +1,250 @@
+// References:
+// - Enhanced LRC (A2 extension):
+//   https://en.wikipedia.org/wiki/LRC_(file_format)#A2_extension:_word_time_tag
+// - ID3v2.3 USLT / SYLT frames:
+//   https://id3.org/id3v2.3.0#Unsychronised_lyrics.2Ftext_transcription
+
+use super::{format_lrc_timestamp, LyricLine, Lyrics};
+
+const ID3_ENCODING_LATIN1: u8 = 0;
+const ID3_ENCODING_UTF16: u8 = 1;
+const ID3_SYLT_FORMAT_MS: u8 = 2;
+const ID3_SYLT_CONTENT_LYRICS: u8 = 1;
+
+/// Text encoder for a single frame; all strings share the encoding byte.
+struct ID3TextEncoder {
+    encoding: u8,
+}
+
+impl ID3TextEncoder {
+    fn new<'a, I: IntoIterator<Item = &'a str>>(texts: I) -> Self {
+        let encoding = if texts.into_iter().all(str::is_ascii) {
+            ID3_ENCODING_LATIN1
+        } else {
+            ID3_ENCODING_UTF16
+        };
+        Self { encoding }
+    }
+
+    fn push(&self, out: &mut Vec<u8>, text: &str, terminate: bool) {
+        if self.encoding == ID3_ENCODING_LATIN1 {
+            out.extend_from_slice(text.as_bytes());
+            if terminate {
+                out.push(0);
+            }
+        } else {
+            out.extend_from_slice(&[0xFF, 0xFE]);
+            for v in text.encode_utf16() {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+            if terminate {
+                out.extend_from_slice(&[0, 0]);
+            }
+        }
+    }
+}
+
+impl Lyrics {
+    fn push_tags(&self, out: &mut String) {
+        for (key, value) in self.tags.iter() {
+            out.push_str(&format!("[{}:{}]\n", key, value));
+        }
+    }
+
+    fn push_lrc_line(out: &mut String, start_ms: u64, text: &str) {
+        out.push_str(&format!("[{}]{}\n", format_lrc_timestamp(start_ms), text));
+    }
+
+    /// Plain LRC, one `[mm:ss.xx]` per line.
+    pub fn to_lrc(&self) -> String {
+        let mut result = String::new();
+        self.push_tags(&mut result);
+        for line in self.lines.iter() {
+            Self::push_lrc_line(&mut result, line.start_ms, &line.text);
+        }
+        result
+    }
+
+    /// Plain LRC of `translations[index]`, timed as the original lines.
+    pub fn translation_to_lrc(&self, index: usize) -> Option<String> {
+        let translation = self.translations.get(index)?;
+
+        let mut result = String::new();
+        self.push_tags(&mut result);
+        for (line, text) in self.lines.iter().zip(translation.lines.iter()) {
+            Self::push_lrc_line(&mut result, line.start_ms, text);
+        }
+        Some(result)
+    }
+
+    /// Enhanced LRC, with a `<mm:ss.xx>` tag before each word
+    ///   and after the last one.
+    pub fn to_enhanced_lrc(&self) -> String {
+        let mut result = String::new();
+        self.push_tags(&mut result);
+        for line in self.lines.iter() {
+            let text = match line.words.last() {
+                Some(last) => {
+                    let mut text = String::new();
+                    for word in line.words.iter() {
+                        text.push_str(&format!(
+                            "<{}>{}",
+                            format_lrc_timestamp(word.start_ms),
+                            word.text
+                        ));
+                    }
+                    let end_ms = last.start_ms + last.duration_ms;
+                    text.push_str(&format!("<{}>", format_lrc_timestamp(end_ms)));
+                    text
+                }
+                None => line.text.clone(),
+            };
+            Self::push_lrc_line(&mut result, line.start_ms, &text);
+        }
+        result
+    }
+
+    /// Payload of an ID3v2 `USLT` frame, without the frame header.
+    pub fn to_id3_uslt_payload(&self, language: &[u8; 3]) -> Vec<u8> {
+        let text = self
+            .lines
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let encoder = ID3TextEncoder::new([text.as_str()]);
+        let mut result = vec![encoder.encoding];
+        result.extend_from_slice(language);
+        encoder.push(&mut result, "", true);
+        encoder.push(&mut result, &text, false);
+        result
+    }
+
+    /// Payload of an ID3v2 `SYLT` frame, without the frame header.
+    ///
+    /// Words are synced individually when timed; new lines start with `\n`.
+    pub fn to_id3_sylt_payload(&self, language: &[u8; 3]) -> Vec<u8> {
+        let items = self
+            .lines
+            .iter()
+            .enumerate()
+            .flat_map(|(i, line)| sylt_items(line, i != 0))
+            .collect::<Vec<_>>();
+
+        let encoder = ID3TextEncoder::new(items.iter().map(|(text, _)| text.as_str()));
+        let mut result = vec![encoder.encoding];
+        result.extend_from_slice(language);
+        result.push(ID3_SYLT_FORMAT_MS);
+        result.push(ID3_SYLT_CONTENT_LYRICS);
+        encoder.push(&mut result, "", true);
+        for (text, start_ms) in items.iter() {
+            encoder.push(&mut result, text, true);
+            result.extend_from_slice(&(*start_ms as u32).to_be_bytes());
+        }
+        result
+    }
+}
+
+fn sylt_items(line: &LyricLine, new_line: bool) -> Vec<(String, u64)> {
+    let prefix = if new_line { "\n" } else { "" };
+
+    if line.words.is_empty() {
+        return vec![(format!("{}{}", prefix, line.text), line.start_ms)];
+    }
+
+    line.words
+        .iter()
+        .enumerate()
+        .map(|(i, word)| {
+            let prefix = if i == 0 { prefix } else { "" };
+            (format!("{}{}", prefix, word.text), word.start_ms)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lyrics::{LyricLine, LyricTranslation, LyricTranslationType, LyricWord, Lyrics};
+
+    fn make_test_lyrics() -> Lyrics {
+        Lyrics {
+            tags: vec![("ti".into(), "Parakeet".into())],
+            lines: vec![
+                LyricLine {
+                    start_ms: 1000,
+                    duration_ms: 1500,
+                    text: "Hello world".into(),
+                    words: vec![
+                        LyricWord {
+                            start_ms: 1000,
+                            duration_ms: 500,
+                            text: "Hello ".into(),
+                        },
+                        LyricWord {
+                            start_ms: 1500,
+                            duration_ms: 1000,
+                            text: "world".into(),
+                        },
+                    ],
+                },
+                LyricLine {
+                    start_ms: 63_450,
+                    duration_ms: 0,
+                    text: "Bye".into(),
+                    words: vec![],
+                },
+            ],
+            translations: vec![LyricTranslation {
+                translation_type: LyricTranslationType::Translation,
+                lines: vec!["Hallo Welt".into(), "Tschüss".into()],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_lrc_export() {
+        let lyrics = make_test_lyrics();
+        assert_eq!(
+            lyrics.to_lrc(),
+            "[ti:Parakeet]\n[00:01.00]Hello world\n[01:03.45]Bye\n"
+        );
+        assert_eq!(
+            lyrics.to_enhanced_lrc(),
+            "[ti:Parakeet]\n[00:01.00]<00:01.00>Hello <00:01.50>world<00:02.50>\n[01:03.45]Bye\n"
+        );
+        assert_eq!(
+            lyrics.translation_to_lrc(0).unwrap(),
+            "[ti:Parakeet]\n[00:01.00]Hallo Welt\n[01:03.45]Tschüss\n"
+        );
+        assert!(lyrics.translation_to_lrc(1).is_none());
+    }
+
+    #[test]
+    fn test_id3_export() {
+        let lyrics = make_test_lyrics();
+
+        assert_eq!(
+            lyrics.to_id3_uslt_payload(b"eng"),
+            b"\x00eng\x00Hello world\nBye".to_vec()
+        );
+
+        let expected: &[u8] = &[
+            0x00, b'e', b'n', b'g', 0x02, 0x01, 0x00, // header, empty descriptor
+            b'H', b'e', b'l', b'l', b'o', b' ', 0x00, 0x00, 0x00, 0x03, 0xE8, // 1000
+            b'w', b'o', b'r', b'l', b'd', 0x00, 0x00, 0x00, 0x05, 0xDC, // 1500
+            b'\n', b'B', b'y', b'e', 0x00, 0x00, 0x00, 0xF7, 0xDA, // 63450
+        ];
+        assert_eq!(lyrics.to_id3_sylt_payload(b"eng"), expected);
+
+        let mut lyrics = lyrics;
+        lyrics.lines.truncate(1);
+        lyrics.lines[0].words.clear();
+        lyrics.lines[0].text = "Grüße".into();
+        let payload = lyrics.to_id3_uslt_payload(b"deu");
+        assert_eq!(
+            payload,
+            b"\x01deu\xFF\xFE\x00\x00\xFF\xFEG\x00r\x00\xFC\x00\xDF\x00e\x00".to_vec()
+        );
+    }
+}
@@ -0,0 +1,304 @@
+// Kugou KRC lyrics.
+//
+// File format:
+//   0000h: "krc1"
+//   0004h: zlib stream, XOR-ed with a 16 byte key
+//
+// Decompressed text:
+//   [ti:title]
+//   [language:base64(json)]
+//   [start,duration]<offset,duration,0>word<offset,duration,0>word...
+//
+// Word offsets are relative to the line start; `LyricWord` times are absolute.
+// `language` holds the translation and romanization, one entry per line
+//   (translation) or per word (romanization).
+
+use crate::lyrics::{LyricLine, LyricTranslation, LyricTranslationType, Lyrics};
+
+pub const KRC_KEY_SIZE: usize = 16;
+
+pub type KRCKey = [u8; KRC_KEY_SIZE];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KRCTranslationType {
+    /// One entry per word.
+    Romanization,
+    /// One entry per line.
+    Translation,
+    Unknown(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KRCTranslation {
+    pub translation_type: KRCTranslationType,
+    /// Language id as given by Kugou.
+    pub language: u64,
+    /// Entries for each lyric line, in the same order as `KRCLyrics::lines`.
+    pub lines: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KRCLyrics {
+    /// Decompressed lyric text.
+    pub text: String,
+    /// Header tags such as `ti`, `ar` or `offset`, in file order.
+    ///   The raw `language` tag is kept here as well.
+    pub tags: Vec<(String, String)>,
+    pub lines: Vec<LyricLine>,
+    pub translations: Vec<KRCTranslation>,
+}
+
+impl From<KRCLyrics> for Lyrics {
+    /// Translations of an unknown type are dropped.
+    fn from(krc: KRCLyrics) -> Self {
+        let translations = krc
+            .translations
+            .into_iter()
+            .filter_map(|translation| {
+                let translation_type = match translation.translation_type {
+                    KRCTranslationType::Translation => LyricTranslationType::Translation,
+                    KRCTranslationType::Romanization => LyricTranslationType::Romanization,
+                    KRCTranslationType::Unknown(_) => return None,
+                };
+                Some(LyricTranslation {
+                    translation_type,
+                    lines: translation
+                        .lines
+                        .iter()
+                        .map(|words| words.concat())
+                        .collect(),
+                })
+            })
+            .collect();
+
+        Lyrics {
+            tags: krc
+                .tags
+                .into_iter()
+                .filter(|(key, _)| key != "language")
+                .collect(),
+            lines: krc.lines,
+            translations,
+        }
+    }
+}
+
+mod detail {
+    use serde_json::Value;
+
+    use super::{KRCKey, KRCLyrics, KRCTranslation, KRCTranslationType};
+    use crate::{
+        decryptor::{DecryptError, DecryptErrorCode},
+        lyrics::{inflate_text, parse_tag, parse_time_pair, LyricLine, LyricWord},
+        netease::music_info::value_to_u64,
+        utils::array_ext::ByteSliceExt,
+    };
+
+    const KRC_MAGIC: &[u8; 4] = b"krc1";
+    const LANGUAGE_TAG: &str = "language";
+
+    fn invalid_lyrics(str: &str) -> DecryptError {
+        DecryptError::new(DecryptErrorCode::InvalidLyrics, str)
+    }
+
+    pub fn decode_krc(data: &[u8], key: &KRCKey) -> Result<KRCLyrics, DecryptError> {
+        if !data.starts_with(KRC_MAGIC) {
+            return Err(DecryptError::new(
+                DecryptErrorCode::UnknownMagicHeader,
+                "krc: unknown magic header",
+            ));
+        }
+
+        let mut buf = data[KRC_MAGIC.len()..].to_vec();
+        buf.xor_key(key);
+        parse_krc_text(inflate_text(&buf, "krc")?)
+    }
+
+    /// Parse decompressed KRC text.
+    pub fn parse_krc_text(text: String) -> Result<KRCLyrics, DecryptError> {
+        let mut tags = vec![];
+        let mut lines = vec![];
+        let mut translations = vec![];
+
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if let Some(line) = parse_line(line) {
+                lines.push(line);
+            } else if let Some((key, value)) = parse_tag(line) {
+                if key == LANGUAGE_TAG && !value.is_empty() {
+                    translations = parse_language(value)?;
+                }
+                tags.push((key.into(), value.into()));
+            }
+        }
+
+        Ok(KRCLyrics {
+            text,
+            tags,
+            lines,
+            translations,
+        })
+    }
+
+    /// `[start,duration]<offset,duration,0>word...`
+    fn parse_line(line: &str) -> Option<LyricLine> {
+        let (header, mut rest) = line.strip_prefix('[')?.split_once(']')?;
+        let (start_ms, duration_ms) = parse_time_pair(header)?;
+
+        let mut words = vec![];
+        while let Some(timing) = rest.strip_prefix('<') {
+            let (timing, after) = timing.split_once('>')?;
+            let (offset_ms, word_duration_ms) =
+                parse_time_pair(timing.rsplit_once(',').map_or(timing, |(t, _)| t))?;
+            let text_end = after.find('<').unwrap_or(after.len());
+
+            words.push(LyricWord {
+                start_ms: start_ms + offset_ms,
+                duration_ms: word_duration_ms,
+                text: after[..text_end].into(),
+            });
+            rest = &after[text_end..];
+        }
+
+        let text = if words.is_empty() {
+            rest.into()
+        } else {
+            words.iter().map(|w| w.text.as_str()).collect()
+        };
+
+        Some(LyricLine {
+            start_ms,
+            duration_ms,
+            text,
+            words,
+        })
+    }
+
+    /// `{"content":[{"type":1,"language":0,"lyricContent":[["line"],...]}],"version":1}`
+    fn parse_language(encoded: &str) -> Result<Vec<KRCTranslation>, DecryptError> {
+        let json =
+            base64::decode(encoded).map_err(|_| invalid_lyrics("krc: invalid language base64"))?;
+        let json: Value = serde_json::from_slice(&json)
+            .map_err(|_| invalid_lyrics("krc: invalid language json"))?;
+
+        let content = json
+            .get("content")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid_lyrics("krc: language content not found"))?;
+
+        // Entries without a type are skipped.
+        let translations = content
+            .iter()
+            .filter_map(|item| {
+                let translation_type = match value_to_u64(item.get("type")?) {
+                    0 => KRCTranslationType::Romanization,
+                    1 => KRCTranslationType::Translation,
+                    v => KRCTranslationType::Unknown(v),
+                };
+
+                let lines = item
+                    .get("lyricContent")
+                    .and_then(Value::as_array)
+                    .map(|lines| {
+                        lines
+                            .iter()
+                            .map(|line| {
+                                line.as_array()
+                                    .map(|words| {
+                                        words
+                                            .iter()
+                                            .map(|w| w.as_str().unwrap_or_default().into())
+                                            .collect()
+                                    })
+                                    .unwrap_or_default()
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                Some(KRCTranslation {
+                    translation_type,
+                    language: item.get("language").map(value_to_u64).unwrap_or_default(),
+                    lines,
+                })
+            })
+            .collect();
+
+        Ok(translations)
+    }
+}
+
+pub use detail::decode_krc;
+pub use detail::parse_krc_text;
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::{decode_krc, KRCKey, KRCTranslationType};
+    use crate::{
+        lyrics::{LyricWord, Lyrics},
+        utils::array_ext::ByteSliceExt,
+    };
+
+    const TEST_KEY: &KRCKey = b"parakeet krc key";
+
+    #[test]
+    fn test_krc() {
+        let language = base64::encode(
+            r#"{"content":[{"language":0,"type":1,"lyricContent":[["Hallo Welt"],["Tschüss"]]}],"version":1}"#,
+        );
+        let text = format!(
+            "\u{feff}[ti:Parakeet]\n[ar:Kugou]\n[language:{}]\n\
+             [1000,1500]<0,500,0>Hello <500,1000,0>world\n\
+             [3000,800]<0,800,0>Bye\n",
+            language
+        );
+
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        let mut body = encoder.finish().unwrap();
+        body.xor_key(TEST_KEY);
+        let mut data = b"krc1".to_vec();
+        data.extend(body);
+
+        let lyrics = decode_krc(&data, TEST_KEY).unwrap();
+        assert_eq!(lyrics.text, &text[3..]);
+        assert_eq!(lyrics.tags[0], ("ti".into(), "Parakeet".into()));
+        assert_eq!(lyrics.tags[2].0, "language");
+
+        assert_eq!(lyrics.lines.len(), 2);
+        let line = &lyrics.lines[0];
+        assert_eq!((line.start_ms, line.duration_ms), (1000, 1500));
+        assert_eq!(line.text, "Hello world");
+        assert_eq!(
+            line.words[1],
+            LyricWord {
+                start_ms: 1500,
+                duration_ms: 1000,
+                text: "world".into()
+            }
+        );
+
+        assert_eq!(lyrics.translations.len(), 1);
+        let translation = &lyrics.translations[0];
+        assert_eq!(
+            translation.translation_type,
+            KRCTranslationType::Translation
+        );
+        assert_eq!(
+            translation.lines,
+            vec![vec!["Hallo Welt".to_string()], vec!["Tschüss".to_string()]]
+        );
+
+        let lyrics = Lyrics::from(lyrics);
+        assert_eq!(lyrics.tags.len(), 2);
+        assert_eq!(lyrics.translations[0].lines[1], "Tschüss");
+        assert_eq!(
+            lyrics.translation_to_lrc(0).unwrap(),
+            "[ti:Parakeet]\n[ar:Kugou]\n[00:01.00]Hallo Welt\n[00:03.00]Tschüss\n"
+        );
+
+        assert!(decode_krc(b"krc2", TEST_KEY).is_err());
+        assert!(decode_krc(b"krc1\x00\x01\x02", TEST_KEY).is_err());
+    }
+}
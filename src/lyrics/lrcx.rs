@@ -0,0 +1,255 @@
+// Kuwo LRCX lyrics.
+//
+// Layers, outermost first: zlib, base64, XOR with "yeelion".
+//   The resulting text is GB18030.
+//
+// Decoded text is LRC with word timing:
+//   [kuwo:0462]
+//   [mm:ss.xx]<a,b>word<a,b>word...
+//
+// The `kuwo` tag is an octal number `k`; with `k1 = k / 10` and `k2 = k % 10`,
+//   each word starts `(a + b) / (k1 * 2)` ms after its line and lasts
+//   `(a - b) / (k2 * 2)` ms. Without the tag, `a` and `b` are used as is.
+
+use crate::lyrics::{LyricLine, Lyrics};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LRCXLyrics {
+    /// Plain LRC, without word timing.
+    pub lrc: String,
+    /// Header tags such as `ti`, `ar` or `kuwo`, in file order.
+    pub tags: Vec<(String, String)>,
+    /// Sorted by start time.
+    pub lines: Vec<LyricLine>,
+}
+
+impl From<LRCXLyrics> for Lyrics {
+    fn from(lrcx: LRCXLyrics) -> Self {
+        Lyrics {
+            tags: lrcx
+                .tags
+                .into_iter()
+                .filter(|(key, _)| key != "kuwo")
+                .collect(),
+            lines: lrcx.lines,
+            translations: vec![],
+        }
+    }
+}
+
+mod detail {
+    use super::LRCXLyrics;
+    use crate::{
+        decryptor::{DecryptError, DecryptErrorCode},
+        lyrics::{
+            format_lrc_timestamp, inflate, parse_lrc_timestamp, parse_tag, LyricLine, LyricWord,
+        },
+        utils::array_ext::ByteSliceExt,
+    };
+
+    const KUWO_LRCX_KEY: &[u8; 7] = b"yeelion";
+    const KUWO_TAG: &str = "kuwo";
+
+    pub fn decode_lrcx(data: &[u8]) -> Result<LRCXLyrics, DecryptError> {
+        let encoded = inflate(data, "lrcx")?;
+        let mut buf = base64::decode(encoded.trim_ascii()).map_err(|_| {
+            DecryptError::new(DecryptErrorCode::InvalidLyrics, "lrcx: invalid base64 data")
+        })?;
+        buf.xor_key(KUWO_LRCX_KEY);
+
+        // Invalid sequences become U+FFFD instead of failing the whole file.
+        let (text, _, _) = encoding_rs::GB18030.decode(&buf);
+        Ok(parse_lrcx_text(text.trim_start_matches('\u{feff}')))
+    }
+
+    /// Word timing factors from the `kuwo` tag, `None` if missing or invalid.
+    fn parse_kuwo_tag(value: &str) -> Option<(i64, i64)> {
+        let k = i64::from_str_radix(value, 8).ok()?;
+        let (k1, k2) = (k / 10, k % 10);
+        (k1 != 0 && k2 != 0).then_some((k1, k2))
+    }
+
+    /// Parse decompressed LRCX text.
+    pub fn parse_lrcx_text(text: &str) -> LRCXLyrics {
+        let mut tags: Vec<(String, String)> = vec![];
+        let mut lines = vec![];
+
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let mut rest = line;
+            let mut timestamps = vec![];
+            while let Some((stamp, after)) = rest.strip_prefix('[').and_then(|r| r.split_once(']'))
+            {
+                match parse_lrc_timestamp(stamp) {
+                    Some(ms) => timestamps.push(ms),
+                    None => break,
+                }
+                rest = after;
+            }
+
+            if timestamps.is_empty() {
+                if let Some((key, value)) = parse_tag(line) {
+                    tags.push((key.into(), value.into()));
+                }
+                continue;
+            }
+
+            for start_ms in timestamps {
+                lines.push((start_ms, rest));
+            }
+        }
+
+        let factors = tags
+            .iter()
+            .find(|(key, _)| key == KUWO_TAG)
+            .and_then(|(_, value)| parse_kuwo_tag(value));
+
+        lines.sort_by_key(|(start_ms, _)| *start_ms);
+        let mut lines = lines
+            .into_iter()
+            .map(|(start_ms, body)| parse_line(start_ms, body, factors))
+            .collect::<Vec<_>>();
+
+        // Lines last until the next one; the last one until its last word ends.
+        let next_starts = lines.iter().skip(1).map(|l| Some(l.start_ms)).chain([None]);
+        let durations = next_starts
+            .zip(lines.iter())
+            .map(|(next_start, line)| match next_start {
+                Some(next_start) => next_start - line.start_ms,
+                None => line
+                    .words
+                    .last()
+                    .map(|w| (w.start_ms + w.duration_ms).saturating_sub(line.start_ms))
+                    .unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+        for (line, duration_ms) in lines.iter_mut().zip(durations) {
+            line.duration_ms = duration_ms;
+        }
+
+        let mut lrc = String::new();
+        for (key, value) in tags.iter().filter(|(key, _)| key != KUWO_TAG) {
+            lrc.push_str(&format!("[{}:{}]\n", key, value));
+        }
+        for line in lines.iter() {
+            lrc.push_str(&format!(
+                "[{}]{}\n",
+                format_lrc_timestamp(line.start_ms),
+                line.text
+            ));
+        }
+
+        LRCXLyrics { lrc, tags, lines }
+    }
+
+    /// `<a,b>word<a,b>word...`, or plain text.
+    fn parse_line(start_ms: u64, body: &str, factors: Option<(i64, i64)>) -> LyricLine {
+        let mut words = vec![];
+        let mut rest = body;
+
+        while let Some((timing, after)) = rest.strip_prefix('<').and_then(|r| r.split_once('>')) {
+            let Some((a, b)) = timing.split_once(',').and_then(|(a, b)| {
+                Some((a.trim().parse::<i64>().ok()?, b.trim().parse::<i64>().ok()?))
+            }) else {
+                break;
+            };
+
+            let (offset_ms, duration_ms) = match factors {
+                Some((k1, k2)) => ((a + b) / (k1 * 2), (a - b) / (k2 * 2)),
+                None => (a, b),
+            };
+            let text_end = after.find('<').unwrap_or(after.len());
+
+            words.push(LyricWord {
+                start_ms: start_ms + offset_ms.max(0) as u64,
+                duration_ms: duration_ms.max(0) as u64,
+                text: after[..text_end].into(),
+            });
+            rest = &after[text_end..];
+        }
+
+        let text = if words.is_empty() {
+            body.into()
+        } else {
+            words.iter().map(|w| w.text.as_str()).collect()
+        };
+
+        LyricLine {
+            start_ms,
+            duration_ms: 0,
+            text,
+            words,
+        }
+    }
+}
+
+pub use detail::decode_lrcx;
+pub use detail::parse_lrcx_text;
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::decode_lrcx;
+    use crate::{lyrics::LyricWord, utils::array_ext::ByteSliceExt};
+
+    fn encode_lrcx(text: &str) -> Vec<u8> {
+        let (text, _, _) = encoding_rs::GB18030.encode(text);
+        let mut data = text.into_owned();
+        data.xor_key(b"yeelion");
+
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(base64::encode(data).as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_lrcx() {
+        // kuwo = 0o462 = 306: k1 = 30, k2 = 6
+        let text = "[ti:Parakeet]\n[kuwo:0462]\n\
+                    [00:01.00]<15000,-15000>Hello <30000,0>world\n\
+                    [00:03.50]<0,0>Bye\n";
+        let lyrics = decode_lrcx(&encode_lrcx(text)).unwrap();
+
+        assert_eq!(
+            lyrics.lrc,
+            "[ti:Parakeet]\n[00:01.00]Hello world\n[00:03.50]Bye\n"
+        );
+        assert_eq!(lyrics.lines.len(), 2);
+
+        let line = &lyrics.lines[0];
+        assert_eq!((line.start_ms, line.duration_ms), (1000, 2500));
+        assert_eq!(
+            line.words,
+            vec![
+                LyricWord {
+                    start_ms: 1000,
+                    duration_ms: 2500,
+                    text: "Hello ".into()
+                },
+                LyricWord {
+                    start_ms: 1500,
+                    duration_ms: 2500,
+                    text: "world".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lrcx_plain() {
+        let text = "[ar:酷我]\n[00:02.00][00:00.50]Repeat\n[00:01.123]Plain line\n";
+        let lyrics = decode_lrcx(&encode_lrcx(text)).unwrap();
+
+        let starts = lyrics.lines.iter().map(|l| l.start_ms).collect::<Vec<_>>();
+        assert_eq!(starts, vec![500, 1123, 2000]);
+        assert!(lyrics.lines.iter().all(|l| l.words.is_empty()));
+        assert_eq!(lyrics.lines[1].text, "Plain line");
+        assert_eq!(lyrics.tags, vec![("ar".into(), "酷我".into())]);
+
+        assert!(decode_lrcx(b"not zlib").is_err());
+
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(b"not base64!").unwrap();
+        assert!(decode_lrcx(&encoder.finish().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+mod export;
+pub mod krc;
+pub mod lrcx;
+
+/// A single timed word (or syllable) of a lyric line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LyricWord {
+    /// Absolute start time, in milliseconds.
+    pub start_ms: u64,
+    pub duration_ms: u64,
+    pub text: String,
+}
+
+/// A lyric line; `words` is empty when the source has no word timing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LyricLine {
+    /// Absolute start time, in milliseconds.
+    pub start_ms: u64,
+    pub duration_ms: u64,
+    pub text: String,
+    pub words: Vec<LyricWord>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LyricTranslationType {
+    Translation,
+    Romanization,
+}
+
+/// Alternative text for each lyric line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LyricTranslation {
+    pub translation_type: LyricTranslationType,
+    /// One entry per `Lyrics::lines` item, in the same order.
+    pub lines: Vec<String>,
+}
+
+/// Lyrics decoded from any of the supported platforms.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lyrics {
+    /// Header tags such as `ti`, `ar` or `al`, in file order.
+    pub tags: Vec<(String, String)>,
+    pub lines: Vec<LyricLine>,
+    pub translations: Vec<LyricTranslation>,
+}
+
+mod detail {
+    use std::io::Read;
+
+    use crate::decryptor::{DecryptError, DecryptErrorCode};
+
+    /// Decompress a zlib stream.
+    pub fn inflate(buf: &[u8], name: &str) -> Result<Vec<u8>, DecryptError> {
+        let mut result = vec![];
+        flate2::read::ZlibDecoder::new(buf)
+            .read_to_end(&mut result)
+            .map_err(|e| {
+                DecryptError::new(
+                    DecryptErrorCode::DecompressError,
+                    &format!("{}: zlib decompression failed: {}", name, e),
+                )
+            })?;
+        Ok(result)
+    }
+
+    /// Decompress a zlib stream into UTF-8 text, dropping a leading BOM.
+    pub fn inflate_text(buf: &[u8], name: &str) -> Result<String, DecryptError> {
+        let text = String::from_utf8(inflate(buf, name)?).map_err(|_| {
+            DecryptError::new(
+                DecryptErrorCode::InvalidLyrics,
+                &format!("{}: invalid utf-8 text", name),
+            )
+        })?;
+
+        match text.strip_prefix('\u{feff}') {
+            Some(stripped) => Ok(stripped.into()),
+            None => Ok(text),
+        }
+    }
+
+    /// `[key:value]` header tag.
+    pub fn parse_tag(line: &str) -> Option<(&str, &str)> {
+        let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+        let (key, value) = inner.split_once(':')?;
+        Some((key.trim(), value.trim()))
+    }
+
+    /// `mm:ss.xx` or `mm:ss.xxx`, in milliseconds.
+    pub fn parse_lrc_timestamp(s: &str) -> Option<u64> {
+        let (minutes, seconds) = s.split_once(':')?;
+        let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, "0"));
+        if fraction.is_empty() || fraction.len() > 3 {
+            return None;
+        }
+
+        let minutes: u64 = minutes.trim().parse().ok()?;
+        let seconds: u64 = seconds.parse().ok()?;
+        let fraction_ms = fraction.parse::<u64>().ok()? * 10u64.pow(3 - fraction.len() as u32);
+        Some((minutes * 60 + seconds) * 1000 + fraction_ms)
+    }
+
+    /// `mm:ss.xx`, rounded down to hundredths.
+    pub fn format_lrc_timestamp(ms: u64) -> String {
+        format!(
+            "{:02}:{:02}.{:02}",
+            ms / 60_000,
+            ms / 1000 % 60,
+            ms % 1000 / 10
+        )
+    }
+
+    /// `start,duration`, in milliseconds.
+    pub fn parse_time_pair(s: &str) -> Option<(u64, u64)> {
+        let (start, duration) = s.split_once(',')?;
+        Some((start.trim().parse().ok()?, duration.trim().parse().ok()?))
+    }
+}
+
+pub(crate) use detail::{
+    format_lrc_timestamp, inflate, inflate_text, parse_lrc_timestamp, parse_tag, parse_time_pair,
+};
@@ -0,0 +1,89 @@
+/// Information from the `.idx`/`.info` JSON sidecar of a NetEase `.uc` cache file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NCMCacheInfo {
+    pub music_id: u64,
+    /// Expected size of the complete cache file, in bytes; `0` if unknown.
+    pub file_size: u64,
+    pub md5: String,
+}
+
+impl NCMCacheInfo {
+    /// Whether a cache file of `cache_size` bytes has been fully downloaded.
+    /// Always `false` when the expected size is unknown.
+    pub fn is_complete(&self, cache_size: u64) -> bool {
+        self.file_size != 0 && cache_size >= self.file_size
+    }
+}
+
+mod detail {
+    /**
+     * Different client versions use slightly different field names, e.g.:
+     *
+     *   {"musicId":"441491828","size":"8571542","md5":"..."}
+     *   {"songId":441491828,"filesize":8571542,"md5":"..."}
+     *
+     * Numbers may be stored as strings or numbers.
+     */
+    use super::NCMCacheInfo;
+    use crate::{
+        decryptor::{DecryptError, DecryptErrorCode},
+        netease::music_info::{value_to_string, value_to_u64},
+    };
+    use serde_json::Value;
+
+    const MUSIC_ID_KEYS: &[&str] = &["musicId", "songId", "id"];
+    const FILE_SIZE_KEYS: &[&str] = &["size", "fileSize", "filesize"];
+
+    #[inline]
+    fn first_u64(json: &Value, keys: &[&str]) -> u64 {
+        keys.iter()
+            .map(|key| value_to_u64(&json[key]))
+            .find(|v| *v != 0)
+            .unwrap_or_default()
+    }
+
+    /// Parse the JSON content of a `.idx` or `.info` cache sidecar file.
+    pub fn parse_ncm_cache_info<T: AsRef<[u8]>>(json: T) -> Result<NCMCacheInfo, DecryptError> {
+        let json: Value = serde_json::from_slice(json.as_ref()).map_err(|_| {
+            DecryptError::new(
+                DecryptErrorCode::NCMInvalidMetadata,
+                "invalid cache info json",
+            )
+        })?;
+
+        Ok(NCMCacheInfo {
+            music_id: first_u64(&json, MUSIC_ID_KEYS),
+            file_size: first_u64(&json, FILE_SIZE_KEYS),
+            md5: value_to_string(&json["md5"]),
+        })
+    }
+}
+
+pub use detail::parse_ncm_cache_info;
+
+#[cfg(test)]
+mod test {
+    use super::{parse_ncm_cache_info, NCMCacheInfo};
+
+    #[test]
+    fn test_parse_cache_info() {
+        let info =
+            parse_ncm_cache_info(br#"{"musicId":"441491828","size":"8571542","md5":"abcd"}"#)
+                .unwrap();
+        assert_eq!(
+            info,
+            NCMCacheInfo {
+                music_id: 441491828,
+                file_size: 8571542,
+                md5: "abcd".into(),
+            }
+        );
+        assert!(!info.is_complete(8571541));
+        assert!(info.is_complete(8571542));
+
+        let info = parse_ncm_cache_info(br#"{"songId":42,"filesize":1024}"#).unwrap();
+        assert_eq!((info.music_id, info.file_size), (42, 1024));
+
+        assert!(parse_ncm_cache_info(b"not json").is_err());
+    }
+}
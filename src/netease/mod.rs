@@ -0,0 +1,2 @@
+pub mod cache_info;
+pub mod music_info;
@@ -0,0 +1,251 @@
+// AES-128
+pub const NCM_META_KEY_SIZE: usize = 128 / 8;
+pub type NCMMetaKey = [u8; NCM_META_KEY_SIZE];
+
+/// Song information carried by NCM files and `163 key` comments.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NCMMusicInfo {
+    pub music_id: u64,
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album: String,
+    pub album_art_url: String,
+    /// Bits per second.
+    pub bitrate: u32,
+    /// Milliseconds.
+    pub duration: u64,
+    /// Audio format name, e.g. `mp3` or `flac`.
+    pub format: String,
+}
+
+mod detail {
+    /**
+     * @brief NetEase "163 key" format
+     *
+     *   163_key   := "163 key(Don't modify):" base64( AES-128-ECB(meta_key, payload) )
+     *   payload   := "music:" [json] | "dj:" [json]
+     *
+     * NCM files store `163_key` XOR-ed with 0x63 in their metadata block.
+     * For `dj:` entries, the song itself is found under `mainMusic`.
+     */
+    use super::{NCMMetaKey, NCMMusicInfo};
+    use crate::{
+        decryptor::{DecryptError, DecryptErrorCode},
+        utils::{aes_ecb::Aes128EcbDec, audio::find_audio_comments},
+    };
+    use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyInit};
+    use serde_json::Value;
+
+    // cspell:disable-next-line
+    const META_KEY_PREFIX: &[u8] = b"163 key(Don't modify):";
+    const META_MUSIC_PREFIX: &[u8] = b"music:";
+    const META_DJ_PREFIX: &[u8] = b"dj:";
+    const NCM_META_XOR_KEY: u8 = 0x63;
+
+    #[inline]
+    fn invalid_metadata(reason: &str) -> DecryptError {
+        DecryptError::new(DecryptErrorCode::NCMInvalidMetadata, reason)
+    }
+
+    #[inline]
+    pub(crate) fn value_to_string(value: &Value) -> String {
+        match value {
+            Value::String(str) => str.clone(),
+            Value::Number(num) => num.to_string(),
+            _ => String::new(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn value_to_u64(value: &Value) -> u64 {
+        match value {
+            Value::Number(num) => num.as_u64().unwrap_or_default(),
+            Value::String(str) => str.parse().unwrap_or_default(),
+            _ => 0,
+        }
+    }
+
+    fn parse_music_json(json: &Value) -> NCMMusicInfo {
+        // artist := [ [name, id], ... ]
+        let artists = json["artist"]
+            .as_array()
+            .map(|artists| {
+                artists
+                    .iter()
+                    .map(|artist| value_to_string(&artist[0]))
+                    .filter(|name| !name.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        NCMMusicInfo {
+            music_id: value_to_u64(&json["musicId"]),
+            title: value_to_string(&json["musicName"]),
+            artists,
+            album: value_to_string(&json["album"]),
+            album_art_url: value_to_string(&json["albumPic"]),
+            bitrate: value_to_u64(&json["bitrate"]) as u32,
+            duration: value_to_u64(&json["duration"]),
+            format: value_to_string(&json["format"]),
+        }
+    }
+
+    /// Decrypt and parse a `163 key(Don't modify):...` string.
+    pub fn parse_163_key<T: AsRef<[u8]>>(
+        comment: T,
+        meta_key: &NCMMetaKey,
+    ) -> Result<NCMMusicInfo, DecryptError> {
+        let comment = comment.as_ref();
+        let encoded = comment
+            .strip_prefix(META_KEY_PREFIX)
+            .ok_or_else(|| invalid_metadata("missing 163 key prefix"))?;
+        let encoded = String::from_utf8_lossy(encoded);
+        let encoded = encoded.trim_end_matches(|c: char| c.is_ascii_whitespace() || c == '\0');
+
+        let encrypted =
+            base64::decode(encoded).map_err(|_| invalid_metadata("invalid base64 data"))?;
+        let mut plain = encrypted.clone();
+        let plain = Aes128EcbDec::new_from_slice(meta_key)
+            .map_err(|_| DecryptError::new(DecryptErrorCode::AESParamError, "invalid key size"))?
+            .decrypt_padded_b2b_mut::<Pkcs7>(&encrypted, &mut plain)
+            .map_err(|_| invalid_metadata("decrypt metadata failed"))?;
+
+        let (is_dj, json) = if let Some(json) = plain.strip_prefix(META_MUSIC_PREFIX) {
+            (false, json)
+        } else if let Some(json) = plain.strip_prefix(META_DJ_PREFIX) {
+            (true, json)
+        } else {
+            return Err(invalid_metadata("unknown metadata type"));
+        };
+
+        let json: Value =
+            serde_json::from_slice(json).map_err(|_| invalid_metadata("invalid metadata json"))?;
+        if is_dj {
+            Ok(parse_music_json(&json["mainMusic"]))
+        } else {
+            Ok(parse_music_json(&json))
+        }
+    }
+
+    /// Parse the metadata block of an NCM file (`163 key` XOR-ed with 0x63).
+    pub fn parse_ncm_metadata<T: AsRef<[u8]>>(
+        block: T,
+        meta_key: &NCMMetaKey,
+    ) -> Result<NCMMusicInfo, DecryptError> {
+        let comment: Vec<u8> = block
+            .as_ref()
+            .iter()
+            .map(|v| v ^ NCM_META_XOR_KEY)
+            .collect();
+        parse_163_key(comment, meta_key)
+    }
+
+    /// Find and parse the `163 key` comment in the header of a plain MP3 (ID3v2 `COMM`)
+    ///   or FLAC (Vorbis comment `DESCRIPTION`) file.
+    /// `header` should contain the complete tag.
+    pub fn parse_audio_163_key<T: AsRef<[u8]>>(
+        header: T,
+        meta_key: &NCMMetaKey,
+    ) -> Result<NCMMusicInfo, DecryptError> {
+        let comment = find_audio_comments(header.as_ref())
+            .into_iter()
+            .find(|comment| comment.as_bytes().starts_with(META_KEY_PREFIX))
+            .ok_or_else(|| invalid_metadata("163 key not found"))?;
+        parse_163_key(comment, meta_key)
+    }
+}
+
+pub use detail::parse_163_key;
+pub use detail::parse_audio_163_key;
+pub use detail::parse_ncm_metadata;
+pub(crate) use detail::{value_to_string, value_to_u64};
+
+#[cfg(test)]
+pub mod test {
+    use super::{NCMMetaKey, NCMMusicInfo, NCM_META_KEY_SIZE};
+    use crate::utils::test_util::test::generate_test_data;
+    use aes::{
+        cipher::{block_padding::Pkcs7, BlockEncrypt, KeyInit},
+        Aes128,
+    };
+
+    pub fn make_163_key(payload: &str, meta_key: &NCMMetaKey) -> String {
+        let payload = payload.as_bytes();
+        let mut encrypted = vec![0u8; (payload.len() / 16 + 1) * 16];
+        let encrypted = Aes128::new_from_slice(meta_key)
+            .unwrap()
+            .encrypt_padded_b2b::<Pkcs7>(payload, &mut encrypted)
+            .unwrap();
+        format!("163 key(Don't modify):{}", base64::encode(encrypted))
+    }
+
+    pub fn make_test_meta_key() -> NCMMetaKey {
+        generate_test_data(NCM_META_KEY_SIZE, "ncm-meta-key")
+            .try_into()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_163_key() {
+        let meta_key = make_test_meta_key();
+        let comment = make_163_key(
+            concat!(
+                r#"music:{"musicId":441491828,"musicName":"Parakeet","#,
+                r#""artist":[["Artist A",1],["Artist B","2"]],"album":"Album","#,
+                r#""albumPic":"https://p1.music.126.net/cover.jpg","bitrate":320000,"#,
+                r#""duration":265000,"format":"mp3"}"#
+            ),
+            &meta_key,
+        );
+
+        let info = super::parse_163_key(comment, &meta_key).unwrap();
+        assert_eq!(
+            info,
+            NCMMusicInfo {
+                music_id: 441491828,
+                title: "Parakeet".into(),
+                artists: vec!["Artist A".into(), "Artist B".into()],
+                album: "Album".into(),
+                album_art_url: "https://p1.music.126.net/cover.jpg".into(),
+                bitrate: 320000,
+                duration: 265000,
+                format: "mp3".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_163_key_dj() {
+        let meta_key = make_test_meta_key();
+        let comment = make_163_key(
+            r#"dj:{"programId":1,"mainMusic":{"musicId":"42","musicName":"Radio"}}"#,
+            &meta_key,
+        );
+
+        let info = super::parse_163_key(comment, &meta_key).unwrap();
+        assert_eq!(info.music_id, 42);
+        assert_eq!(info.title, "Radio");
+    }
+
+    #[test]
+    fn test_parse_audio_163_key() {
+        let meta_key = make_test_meta_key();
+        let comment = make_163_key(r#"music:{"musicId":7,"musicName":"Parakeet"}"#, &meta_key);
+
+        // COMM: encoding(latin1) language description(empty) text
+        let frame = [&b"\x00eng\x00"[..], comment.as_bytes()].concat();
+        let mut mp3 = Vec::from(&b"ID3\x03\x00\x00"[..]);
+        mp3.extend_from_slice(&(frame.len() as u32 + 10).to_be_bytes());
+        mp3.extend_from_slice(b"COMM");
+        mp3.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        mp3.extend_from_slice(&[0, 0]);
+        mp3.extend_from_slice(&frame);
+        mp3.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+
+        let info = super::parse_audio_163_key(&mp3, &meta_key).unwrap();
+        assert_eq!(info.music_id, 7);
+        assert_eq!(info.title, "Parakeet");
+
+        assert!(super::parse_audio_163_key(&mp3[..10], &meta_key).is_err());
+    }
+}
@@ -1 +1,3 @@
 pub mod qmc_footer;
+mod qq_des;
+pub mod qrc;
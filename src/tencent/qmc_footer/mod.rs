@@ -1,5 +1,6 @@
 mod key_derive;
 mod parser;
 
+pub use key_derive::parse_ekey;
 pub use parser::parse;
 pub use parser::QMCFooterParseResult;
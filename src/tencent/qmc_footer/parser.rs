@@ -106,7 +106,7 @@ mod tests {
 
     #[test]
     fn test_parse_small_buffer_boundary_check() {
-        assert_eq!(parse(&[0u8; 7]), None);
-        assert_eq!(parse(&[0u8; 8]), None);
+        assert_eq!(parse([0u8; 7]), None);
+        assert_eq!(parse([0u8; 8]), None);
     }
 }
@@ -0,0 +1,293 @@
+// QQ Music QRC lyrics.
+//
+// Layers, outermost first:
+// - local `.qrc` files only: QMCv1 static cipher, then an 11 byte prefix;
+// - triple DES (QQ variant, see `qq_des`; not yet verified against real files);
+// - zlib;
+// - UTF-8 XML, with the lyric text in the `LyricContent` attribute:
+//     `[start,duration]word(start,duration)word(start,duration)...`
+//
+// Times are absolute, in milliseconds. Keys are not shipped with the crate.
+
+use crate::lyrics::{LyricLine, Lyrics};
+
+pub const QRC_KEY_SIZE: usize = 24;
+pub const QRC_LOCAL_PREFIX_SIZE: usize = 11;
+
+pub type QRCKey = [u8; QRC_KEY_SIZE];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QRCLyrics {
+    /// Decoded XML document.
+    pub xml: String,
+    /// Header tags such as `ti`, `ar` or `offset`, in file order.
+    pub tags: Vec<(String, String)>,
+    pub lines: Vec<LyricLine>,
+}
+
+impl From<QRCLyrics> for Lyrics {
+    fn from(qrc: QRCLyrics) -> Self {
+        Lyrics {
+            tags: qrc.tags,
+            lines: qrc.lines,
+            translations: vec![],
+        }
+    }
+}
+
+mod detail {
+    use super::{QRCKey, QRCLyrics, QRC_LOCAL_PREFIX_SIZE};
+    use crate::{
+        decryption::new_qmc_v1,
+        decryptor::{DecryptError, DecryptErrorCode, Decryptor},
+        lyrics::{inflate_text, parse_tag, parse_time_pair, LyricLine, LyricWord},
+        tencent::qq_des::{QQDesMode, QQTripleDes, QQ_DES_BLOCK_SIZE},
+    };
+
+    const LYRIC_CONTENT_ATTR: &str = "LyricContent=\"";
+
+    /// Decode QRC data as downloaded from the server (after hex decoding).
+    pub fn decode_qrc(data: &[u8], key: &QRCKey) -> Result<QRCLyrics, DecryptError> {
+        if !data.len().is_multiple_of(QQ_DES_BLOCK_SIZE) {
+            return Err(DecryptError::new(
+                DecryptErrorCode::InvalidBlockSize,
+                "qrc: data is not a multiple of the des block size",
+            ));
+        }
+
+        let mut buf = data.to_vec();
+        QQTripleDes::new(key, QQDesMode::Decrypt).crypt(&mut buf);
+
+        parse_qrc_xml(inflate_text(&buf, "qrc")?)
+    }
+
+    /// Decode a local `.qrc` file, which has an extra QMCv1 static cipher layer.
+    pub fn decode_local_qrc(
+        data: &[u8],
+        xor_key: &[u8],
+        key: &QRCKey,
+    ) -> Result<QRCLyrics, DecryptError> {
+        let mut decryptor = new_qmc_v1(xor_key);
+        decryptor.write(data)?;
+        decryptor.end()?;
+        let buf = decryptor.read_all_output();
+
+        if buf.len() < QRC_LOCAL_PREFIX_SIZE {
+            return Err(DecryptError::new(
+                DecryptErrorCode::InvalidLyrics,
+                "qrc: local file too small",
+            ));
+        }
+
+        decode_qrc(&buf[QRC_LOCAL_PREFIX_SIZE..], key)
+    }
+
+    /// Parse a decoded QRC XML document.
+    pub fn parse_qrc_xml(xml: String) -> Result<QRCLyrics, DecryptError> {
+        let content = xml
+            .find(LYRIC_CONTENT_ATTR)
+            .map(|i| &xml[i + LYRIC_CONTENT_ATTR.len()..])
+            .and_then(|rest| rest.find('"').map(|end| &rest[..end]))
+            .ok_or_else(|| {
+                DecryptError::new(
+                    DecryptErrorCode::InvalidLyrics,
+                    "qrc: LyricContent attribute not found",
+                )
+            })?;
+        let content = unescape_xml(content);
+
+        let mut tags = vec![];
+        let mut lines = vec![];
+        for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if let Some(line) = parse_line(line) {
+                lines.push(line);
+            } else if let Some((key, value)) = parse_tag(line) {
+                tags.push((key.into(), value.into()));
+            }
+        }
+
+        Ok(QRCLyrics { xml, tags, lines })
+    }
+
+    /// `[start,duration]word(start,duration)...`
+    fn parse_line(line: &str) -> Option<LyricLine> {
+        let (header, mut rest) = line.strip_prefix('[')?.split_once(']')?;
+        let (start_ms, duration_ms) = parse_time_pair(header)?;
+
+        let mut words = vec![];
+        // Word text may itself contain parentheses,
+        //   so only a `(digits,digits)` group ends a word.
+        let mut search_from = 0;
+        while let Some(open) = rest[search_from..].find('(').map(|i| i + search_from) {
+            let timing = rest[open + 1..].find(')').and_then(|close| {
+                parse_time_pair(&rest[open + 1..open + 1 + close]).zip(Some(close))
+            });
+
+            match timing {
+                Some(((start_ms, duration_ms), close)) => {
+                    words.push(LyricWord {
+                        start_ms,
+                        duration_ms,
+                        text: rest[..open].into(),
+                    });
+                    rest = &rest[open + close + 2..];
+                    search_from = 0;
+                }
+                None => search_from = open + 1,
+            }
+        }
+
+        let text = if words.is_empty() {
+            rest.into()
+        } else {
+            words.iter().map(|w| w.text.as_str()).collect()
+        };
+
+        Some(LyricLine {
+            start_ms,
+            duration_ms,
+            text,
+            words,
+        })
+    }
+
+    fn unescape_xml(s: &str) -> String {
+        let mut result = String::with_capacity(s.len());
+        let mut rest = s;
+
+        while let Some(amp) = rest.find('&') {
+            result.push_str(&rest[..amp]);
+            rest = &rest[amp..];
+
+            let entity = rest.find(';').map(|end| (&rest[1..end], end));
+            let decoded = entity.and_then(|(name, _)| match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => {
+                    let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => name.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                    };
+                    code.and_then(char::from_u32)
+                }
+            });
+
+            match (decoded, entity) {
+                (Some(c), Some((_, end))) => {
+                    result.push(c);
+                    rest = &rest[end + 1..];
+                }
+                _ => {
+                    result.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        result.push_str(rest);
+        result
+    }
+}
+
+pub use detail::decode_local_qrc;
+pub use detail::decode_qrc;
+pub use detail::parse_qrc_xml;
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::{decode_local_qrc, decode_qrc, QRCKey};
+    use crate::{
+        decryption::new_qmc_v1,
+        decryptor::Decryptor,
+        lyrics::LyricWord,
+        tencent::qq_des::{QQDesMode, QQTripleDes, QQ_DES_BLOCK_SIZE},
+    };
+
+    const TEST_KEY: &QRCKey = b"test qrc triple des key!";
+
+    const TEST_XML: &str = concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+        "<QrcInfos>\n",
+        "<QrcHeadInfo SaveTime=\"1\" Version=\"100\"/>\n",
+        "<LyricInfo LyricCount=\"1\">\n",
+        "<Lyric_1 LyricType=\"1\" LyricContent=\"[ti:Parakeet]\n",
+        "[ar:Tom &amp; Jerry]\n",
+        "[offset:0]\n",
+        "[1000,1500]Hello (1000,500)(world)(1500,1000)\n",
+        "[3000,800]&quot;Bye&quot;(3000,800)\n",
+        "\"/>\n",
+        "</LyricInfo>\n",
+        "</QrcInfos>\n",
+    );
+
+    fn encrypt_qrc(xml: &str) -> Vec<u8> {
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(xml.as_bytes()).unwrap();
+        let mut data = encoder.finish().unwrap();
+
+        let padded_len = data.len().div_ceil(QQ_DES_BLOCK_SIZE) * QQ_DES_BLOCK_SIZE;
+        data.resize(padded_len, 0);
+        QQTripleDes::new(TEST_KEY, QQDesMode::Encrypt).crypt(&mut data);
+        data
+    }
+
+    #[test]
+    fn test_qrc() {
+        let lyrics = decode_qrc(&encrypt_qrc(TEST_XML), TEST_KEY).unwrap();
+        assert_eq!(lyrics.xml, TEST_XML);
+        assert_eq!(
+            lyrics.tags,
+            vec![
+                ("ti".to_string(), "Parakeet".to_string()),
+                ("ar".to_string(), "Tom & Jerry".to_string()),
+                ("offset".to_string(), "0".to_string()),
+            ]
+        );
+
+        assert_eq!(lyrics.lines.len(), 2);
+        let line = &lyrics.lines[0];
+        assert_eq!((line.start_ms, line.duration_ms), (1000, 1500));
+        assert_eq!(line.text, "Hello (world)");
+        assert_eq!(
+            line.words,
+            vec![
+                LyricWord {
+                    start_ms: 1000,
+                    duration_ms: 500,
+                    text: "Hello ".into()
+                },
+                LyricWord {
+                    start_ms: 1500,
+                    duration_ms: 1000,
+                    text: "(world)".into()
+                },
+            ]
+        );
+        assert_eq!(lyrics.lines[1].text, "\"Bye\"");
+
+        assert!(decode_qrc(&[0u8; 7], TEST_KEY).is_err());
+        assert!(decode_qrc(&[0u8; 16], TEST_KEY).is_err());
+    }
+
+    #[test]
+    fn test_local_qrc() {
+        let xor_key = (0u8..=255).collect::<Vec<u8>>();
+
+        let mut data = b"[offset:0]\n".to_vec();
+        data.extend(encrypt_qrc(TEST_XML));
+        // The QMCv1 static cipher is symmetric.
+        let mut encryptor = new_qmc_v1(&xor_key);
+        encryptor.write(&data).unwrap();
+        encryptor.end().unwrap();
+        let data = encryptor.read_all_output();
+
+        let lyrics = decode_local_qrc(&data, &xor_key, TEST_KEY).unwrap();
+        assert_eq!(lyrics.lines.len(), 2);
+        assert_eq!(lyrics.tags[0].1, "Parakeet");
+    }
+}
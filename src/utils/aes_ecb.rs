@@ -12,7 +12,7 @@ pub(crate) mod detail {
     use core::fmt;
 
     #[cfg(feature = "zeroize")]
-    use cipher::zeroize::{Zeroize, ZeroizeOnDrop};
+    use cipher::zeroize::ZeroizeOnDrop;
 
     /// ECB mode decryptor.
     #[derive(Clone)]
@@ -3,6 +3,7 @@ use std::mem::size_of;
 use num_traits::PrimInt;
 
 pub trait VecExtension {
+    #[allow(dead_code)]
     fn append_of_size(&mut self, len: usize) -> &mut [u8];
     fn append_data<T: AsRef<[u8]>>(&mut self, data: T) -> &mut [u8];
 }
@@ -22,7 +23,9 @@ impl VecExtension for Vec<u8> {
 }
 
 pub trait IntHelper {
+    #[allow(dead_code)]
     fn from_le_bytes(bytes: &[u8]) -> Self;
+    #[allow(dead_code)]
     fn from_be_bytes(bytes: &[u8]) -> Self;
 
     fn read_le(bytes: &[u8], offset: usize) -> Self;
@@ -130,6 +133,7 @@ pub trait ByteSliceExt {
         self.xor_key_with_key_offset(key, 0)
     }
 
+    #[allow(dead_code)]
     unsafe fn set_unchecked(&mut self, i: usize, value: u8);
 }
 
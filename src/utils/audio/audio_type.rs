@@ -1,23 +1,38 @@
-const AUDIO_TYPE_MASK_LOSSLESS: isize = 1 << 5;
+use std::{fmt, str::FromStr};
 
+/// Discriminants are stable and may be persisted: never renumber a variant,
+///   give new ones the next free value.
 #[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AudioType {
     UnknownType = 0,
 
     // Lossy
-    OGG,
-    AAC,
-    MP3,
-    M4A,
-    M4B,
-    MP4,
+    OGG = 1,
+    AAC = 2,
+    MP3 = 3,
+    M4A = 4,
+    M4B = 5,
+    MP4 = 6,
+    MPC = 7,
+    AC3 = 8,
+    DTS = 9,
+    /// Matroska audio, codec unknown.
+    MKA = 10,
+    /// WebM audio (Opus or Vorbis).
+    WEBM = 11,
 
     // Lossless
-    FLAC = AUDIO_TYPE_MASK_LOSSLESS,
-    DFF,
-    WAV,
-    WMA,
-    APE,
+    FLAC = 32,
+    DFF = 33,
+    WAV = 34,
+    /// Usually lossy; WMA Lossless shares the container.
+    WMA = 35,
+    APE = 36,
+    DSF = 37,
+    AIFF = 38,
+    WV = 39,
+    TTA = 40,
 }
 
 pub trait AudioExtensionName {
@@ -38,7 +53,206 @@ impl AudioExtensionName for AudioType {
             AudioType::WAV => "wav",
             AudioType::WMA => "wma",
             AudioType::APE => "ape",
+            AudioType::MPC => "mpc",
+            AudioType::AC3 => "ac3",
+            AudioType::DTS => "dts",
+            AudioType::MKA => "mka",
+            AudioType::WEBM => "webm",
+            AudioType::DSF => "dsf",
+            AudioType::AIFF => "aiff",
+            AudioType::WV => "wv",
+            AudioType::TTA => "tta",
             _ => "bin",
         }
     }
 }
+
+impl AudioType {
+    /// Every known type, `UnknownType` excluded.
+    pub const ALL: [AudioType; 20] = [
+        AudioType::OGG,
+        AudioType::AAC,
+        AudioType::MP3,
+        AudioType::M4A,
+        AudioType::M4B,
+        AudioType::MP4,
+        AudioType::MPC,
+        AudioType::AC3,
+        AudioType::DTS,
+        AudioType::MKA,
+        AudioType::WEBM,
+        AudioType::FLAC,
+        AudioType::DFF,
+        AudioType::WAV,
+        AudioType::WMA,
+        AudioType::APE,
+        AudioType::DSF,
+        AudioType::AIFF,
+        AudioType::WV,
+        AudioType::TTA,
+    ];
+
+    /// Guess from the container alone: a WAV or AIFF(-C) may still hold
+    ///   compressed audio, use [`super::AudioCodec::is_lossless`] when the codec is known.
+    pub fn is_lossless(&self) -> bool {
+        matches!(
+            self,
+            AudioType::FLAC
+                | AudioType::DFF
+                | AudioType::WAV
+                | AudioType::APE
+                | AudioType::DSF
+                | AudioType::AIFF
+                | AudioType::WV
+                | AudioType::TTA
+        )
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match *self {
+            AudioType::OGG => "audio/ogg",
+            AudioType::AAC => "audio/aac",
+            AudioType::MP3 => "audio/mpeg",
+            AudioType::M4A | AudioType::M4B | AudioType::MP4 => "audio/mp4",
+            AudioType::MPC => "audio/x-musepack",
+            AudioType::AC3 => "audio/ac3",
+            AudioType::DTS => "audio/vnd.dts",
+            AudioType::MKA => "audio/x-matroska",
+            AudioType::WEBM => "audio/webm",
+            AudioType::FLAC => "audio/flac",
+            AudioType::DFF => "audio/x-dff",
+            AudioType::WAV => "audio/wav",
+            AudioType::WMA => "audio/x-ms-wma",
+            AudioType::APE => "audio/x-ape",
+            AudioType::DSF => "audio/x-dsf",
+            AudioType::AIFF => "audio/aiff",
+            AudioType::WV => "audio/x-wavpack",
+            AudioType::TTA => "audio/x-tta",
+            AudioType::UnknownType => "application/octet-stream",
+        }
+    }
+
+    /// Look up a type by file extension, with or without the leading dot.
+    ///   Case-insensitive; common aliases such as `aif` or `opus` are accepted.
+    pub fn from_extension(ext: &str) -> Option<AudioType> {
+        let ext = ext.strip_prefix('.').unwrap_or(ext).to_ascii_lowercase();
+        let alias = match ext.as_str() {
+            "oga" | "opus" => Some(AudioType::OGG),
+            "aif" | "aifc" => Some(AudioType::AIFF),
+            "weba" => Some(AudioType::WEBM),
+            "mp+" | "mpp" => Some(AudioType::MPC),
+            "dsdiff" => Some(AudioType::DFF),
+            "wave" => Some(AudioType::WAV),
+            _ => None,
+        };
+
+        alias.or_else(|| {
+            AudioType::ALL
+                .into_iter()
+                .find(|audio_type| audio_type.to_audio_ext() == ext)
+        })
+    }
+}
+
+impl fmt::Display for AudioType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match *self {
+            AudioType::OGG => "Ogg",
+            AudioType::AAC => "AAC",
+            AudioType::MP3 => "MP3",
+            AudioType::M4A => "MPEG-4 Audio",
+            AudioType::M4B => "MPEG-4 Audiobook",
+            AudioType::MP4 => "MPEG-4",
+            AudioType::MPC => "Musepack",
+            AudioType::AC3 => "Dolby Digital (AC-3)",
+            AudioType::DTS => "DTS",
+            AudioType::MKA => "Matroska Audio",
+            AudioType::WEBM => "WebM Audio",
+            AudioType::FLAC => "FLAC",
+            AudioType::DFF => "DSDIFF",
+            AudioType::WAV => "WAV",
+            AudioType::WMA => "Windows Media Audio",
+            AudioType::APE => "Monkey's Audio",
+            AudioType::DSF => "DSD Stream File",
+            AudioType::AIFF => "AIFF",
+            AudioType::WV => "WavPack",
+            AudioType::TTA => "True Audio",
+            AudioType::UnknownType => "Unknown",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseAudioTypeError(pub String);
+
+impl fmt::Display for ParseAudioTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown audio type: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseAudioTypeError {}
+
+impl FromStr for AudioType {
+    type Err = ParseAudioTypeError;
+
+    /// Accepts a file extension (see `from_extension`)
+    ///   or the `Display` name, case-insensitive.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        AudioType::from_extension(s)
+            .or_else(|| {
+                AudioType::ALL
+                    .into_iter()
+                    .find(|audio_type| audio_type.to_string().eq_ignore_ascii_case(s))
+            })
+            .ok_or_else(|| ParseAudioTypeError(s.into()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::{AudioExtensionName, AudioType};
+
+    #[test]
+    fn test_audio_type_properties() {
+        assert!(AudioType::FLAC.is_lossless());
+        assert!(AudioType::WV.is_lossless());
+        assert!(!AudioType::MP3.is_lossless());
+        assert!(!AudioType::WMA.is_lossless());
+
+        // Values from before `MPC`..`TTA` were added.
+        assert_eq!(AudioType::MP4 as isize, 6);
+        assert_eq!(AudioType::FLAC as isize, 32);
+        assert_eq!(AudioType::WMA as isize, 35);
+        assert_eq!(AudioType::APE as isize, 36);
+        assert!(!AudioType::UnknownType.is_lossless());
+
+        assert_eq!(AudioType::MP3.mime_type(), "audio/mpeg");
+        assert_eq!(AudioType::M4A.mime_type(), "audio/mp4");
+        assert_eq!(AudioType::APE.to_string(), "Monkey's Audio");
+
+        let unique = AudioType::ALL.iter().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), AudioType::ALL.len());
+    }
+
+    #[test]
+    fn test_audio_type_parse() {
+        for audio_type in AudioType::ALL {
+            assert_eq!(
+                AudioType::from_extension(audio_type.to_audio_ext()),
+                Some(audio_type)
+            );
+            assert_eq!(audio_type.to_string().parse(), Ok(audio_type));
+        }
+
+        assert_eq!(AudioType::from_extension(".FLAC"), Some(AudioType::FLAC));
+        assert_eq!(AudioType::from_extension("aif"), Some(AudioType::AIFF));
+        assert_eq!(AudioType::from_extension("bin"), None);
+        assert_eq!(" mp3 ".parse::<AudioType>(), Ok(AudioType::MP3));
+        assert!("midi".parse::<AudioType>().is_err());
+    }
+}
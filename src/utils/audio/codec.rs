@@ -0,0 +1,306 @@
+use super::{AudioExtensionName, AudioType};
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioCodec {
+    UnknownCodec,
+
+    // Ogg
+    Vorbis,
+    Opus,
+    Speex,
+
+    // MP4
+    AAC,
+    ALAC,
+    AC3,
+    EAC3,
+
+    // RIFF / WAVE
+    /// Integer PCM.
+    PCM,
+    /// IEEE float PCM.
+    PCMFloat,
+    ADPCM,
+    ALaw,
+    MuLaw,
+    /// Any other `fmt ` format tag.
+    WaveFormat(u16),
+
+    // Shared
+    FLAC,
+    MP3,
+}
+
+impl AudioCodec {
+    pub fn is_lossless(&self) -> bool {
+        matches!(
+            self,
+            AudioCodec::ALAC | AudioCodec::PCM | AudioCodec::PCMFloat | AudioCodec::FLAC
+        )
+    }
+}
+
+/// Container type detected from the header, with the codec found inside, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AudioFormat {
+    pub container: AudioType,
+    pub codec: AudioCodec,
+}
+
+impl AudioExtensionName for AudioFormat {
+    fn to_audio_ext(&self) -> &str {
+        match (&self.container, self.codec) {
+            (AudioType::OGG | AudioType::WEBM, AudioCodec::Opus) => "opus",
+            (AudioType::OGG, AudioCodec::FLAC) => "oga",
+            (container, _) => container.to_audio_ext(),
+        }
+    }
+}
+
+mod detail {
+    use super::AudioCodec;
+    use crate::utils::array_ext::ByteSliceExt;
+
+    // Ogg page header: "OggS" u8(version) u8(type) u64(granule) u32(serial)
+    //   u32(sequence) u32(crc) u8(segment count) u8[segment count]
+    const OGG_SEGMENT_COUNT_OFFSET: usize = 26;
+    const OGG_SEGMENT_TABLE_OFFSET: usize = 27;
+
+    const MP4_BOX_HEADER_SIZE: usize = 8;
+    const MP4_STSD_ENTRIES_OFFSET: usize = 8; // u8(version) u24(flags) u32(entry count)
+    const MP4_STSD_PATH: [&[u8; 4]; 6] = [b"moov", b"trak", b"mdia", b"minf", b"stbl", b"stsd"];
+
+    const RIFF_HEADER_SIZE: usize = 12; // "RIFF" u32_le(size) "WAVE"
+    const RIFF_CHUNK_HEADER_SIZE: usize = 8;
+    const WAVE_FORMAT_PCM: u16 = 0x0001;
+    const WAVE_FORMAT_ADPCM: u16 = 0x0002;
+    const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+    const WAVE_FORMAT_ALAW: u16 = 0x0006;
+    const WAVE_FORMAT_MULAW: u16 = 0x0007;
+    const WAVE_FORMAT_MPEGLAYER3: u16 = 0x0055;
+    const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+    // Extensible format: the sub-format GUID starts with the actual format tag.
+    const WAVE_FORMAT_EXTENSIBLE_SUB_FORMAT_OFFSET: usize = 24;
+
+    /// Codec of the first logical stream, from its first page.
+    pub fn probe_ogg_codec(buf: &[u8]) -> AudioCodec {
+        let Some(&segment_count) = buf.get(OGG_SEGMENT_COUNT_OFFSET) else {
+            return AudioCodec::UnknownCodec;
+        };
+        let packet = buf
+            .get(OGG_SEGMENT_TABLE_OFFSET + segment_count as usize..)
+            .unwrap_or_default();
+
+        if packet.starts_with(b"\x01vorbis") {
+            AudioCodec::Vorbis
+        } else if packet.starts_with(b"OpusHead") {
+            AudioCodec::Opus
+        } else if packet.starts_with(b"\x7FFLAC") {
+            AudioCodec::FLAC
+        } else if packet.starts_with(b"Speex   ") {
+            AudioCodec::Speex
+        } else {
+            AudioCodec::UnknownCodec
+        }
+    }
+
+    /// Iterate over `(type, body)` of the boxes in `buf`; stops at a truncated box.
+    fn mp4_boxes(mut buf: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+        std::iter::from_fn(move || {
+            if buf.len() < MP4_BOX_HEADER_SIZE {
+                return None;
+            }
+
+            let (header_size, size) = match buf.read_be::<u32>(0) {
+                0 => (MP4_BOX_HEADER_SIZE, buf.len()),
+                1 if buf.len() >= 16 => (16, usize::try_from(buf.read_be::<u64>(8)).ok()?),
+                size => (MP4_BOX_HEADER_SIZE, size as usize),
+            };
+            if size < header_size || size > buf.len() {
+                return None;
+            }
+
+            let result = (&buf[4..8], &buf[header_size..size]);
+            buf = &buf[size..];
+            Some(result)
+        })
+    }
+
+    fn mp4_sample_entry_codec(entry_type: &[u8]) -> AudioCodec {
+        match entry_type {
+            b"mp4a" => AudioCodec::AAC,
+            b"alac" => AudioCodec::ALAC,
+            b"fLaC" => AudioCodec::FLAC,
+            b"Opus" => AudioCodec::Opus,
+            b".mp3" => AudioCodec::MP3,
+            b"ac-3" => AudioCodec::AC3,
+            b"ec-3" => AudioCodec::EAC3,
+            _ => AudioCodec::UnknownCodec,
+        }
+    }
+
+    fn probe_mp4_box(buf: &[u8], path: &[&[u8; 4]]) -> AudioCodec {
+        let Some((&name, rest)) = path.split_first() else {
+            // Inside `stsd`: the first sample entry.
+            return buf
+                .get(MP4_STSD_ENTRIES_OFFSET..)
+                .and_then(|entries| mp4_boxes(entries).next())
+                .map_or(AudioCodec::UnknownCodec, |(entry_type, _)| {
+                    mp4_sample_entry_codec(entry_type)
+                });
+        };
+
+        // Several `trak` boxes may exist; use the first one with an audio sample entry.
+        mp4_boxes(buf)
+            .filter(|(box_type, _)| *box_type == name)
+            .map(|(_, body)| probe_mp4_box(body, rest))
+            .find(|codec| *codec != AudioCodec::UnknownCodec)
+            .unwrap_or(AudioCodec::UnknownCodec)
+    }
+
+    /// Codec of the first audio track; `moov` must be inside `buf`.
+    pub fn probe_mp4_codec(buf: &[u8]) -> AudioCodec {
+        probe_mp4_box(buf, &MP4_STSD_PATH)
+    }
+
+    fn wave_format_codec(format_tag: u16) -> AudioCodec {
+        match format_tag {
+            WAVE_FORMAT_PCM => AudioCodec::PCM,
+            WAVE_FORMAT_ADPCM => AudioCodec::ADPCM,
+            WAVE_FORMAT_IEEE_FLOAT => AudioCodec::PCMFloat,
+            WAVE_FORMAT_ALAW => AudioCodec::ALaw,
+            WAVE_FORMAT_MULAW => AudioCodec::MuLaw,
+            WAVE_FORMAT_MPEGLAYER3 => AudioCodec::MP3,
+            tag => AudioCodec::WaveFormat(tag),
+        }
+    }
+
+    /// Codec from the `fmt ` chunk of a RIFF/WAVE file.
+    pub fn probe_riff_codec(buf: &[u8]) -> AudioCodec {
+        let mut p = buf.get(RIFF_HEADER_SIZE..).unwrap_or_default();
+
+        while p.len() >= RIFF_CHUNK_HEADER_SIZE {
+            let chunk_id = &p[..4];
+            let chunk_size = p.read_le::<u32>(4) as usize;
+            let body = &p[RIFF_CHUNK_HEADER_SIZE..];
+
+            if chunk_id == b"fmt " {
+                if body.len() < 2 {
+                    break;
+                }
+
+                let format_tag = body.read_le::<u16>(0);
+                if format_tag != WAVE_FORMAT_EXTENSIBLE {
+                    return wave_format_codec(format_tag);
+                }
+
+                return match body.get(WAVE_FORMAT_EXTENSIBLE_SUB_FORMAT_OFFSET..) {
+                    Some(sub_format) if sub_format.len() >= 2 => {
+                        wave_format_codec(sub_format.read_le::<u16>(0))
+                    }
+                    _ => AudioCodec::UnknownCodec,
+                };
+            }
+
+            // Chunks are padded to an even size.
+            let next = chunk_size + (chunk_size & 1);
+            p = body.get(next..).unwrap_or_default();
+        }
+
+        AudioCodec::UnknownCodec
+    }
+}
+
+pub(super) use detail::{probe_mp4_codec, probe_ogg_codec, probe_riff_codec};
+
+#[cfg(test)]
+mod test {
+    use super::{probe_mp4_codec, probe_ogg_codec, probe_riff_codec, AudioCodec};
+
+    fn mp4_box(box_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let size = (body.len() + 8) as u32;
+        [&size.to_be_bytes()[..], box_type, body].concat()
+    }
+
+    #[test]
+    fn test_probe_ogg() {
+        let mut page = b"OggS\x00\x02".to_vec();
+        page.resize(26, 0);
+        page.extend_from_slice(&[1, 19]);
+        page.extend_from_slice(b"OpusHead\x01\x02\x38\x01\x80\xBB\x00\x00\x00\x00\x00");
+        assert_eq!(probe_ogg_codec(&page), AudioCodec::Opus);
+
+        page.truncate(28);
+        page.extend_from_slice(b"\x01vorbis\x00\x00\x00\x00");
+        assert_eq!(probe_ogg_codec(&page), AudioCodec::Vorbis);
+
+        assert_eq!(probe_ogg_codec(b"OggS"), AudioCodec::UnknownCodec);
+    }
+
+    #[test]
+    fn test_probe_mp4() {
+        let make_file = |entry_type: &[u8; 4]| {
+            let stsd = [
+                &[0u8, 0, 0, 0, 0, 0, 0, 1][..],
+                &mp4_box(entry_type, &[0; 28]),
+            ]
+            .concat();
+            let stbl = mp4_box(b"stbl", &mp4_box(b"stsd", &stsd));
+            let minf = mp4_box(b"minf", &[mp4_box(b"smhd", &[0; 8]), stbl].concat());
+            let mdia = mp4_box(b"mdia", &[mp4_box(b"mdhd", &[0; 24]), minf].concat());
+            let trak = mp4_box(b"trak", &[mp4_box(b"tkhd", &[0; 84]), mdia].concat());
+            let video_trak = mp4_box(b"trak", &mp4_box(b"tkhd", &[0; 84]));
+            let moov = mp4_box(
+                b"moov",
+                &[mp4_box(b"mvhd", &[0; 100]), video_trak, trak].concat(),
+            );
+            [mp4_box(b"ftyp", b"M4A \x00\x00\x00\x00"), moov].concat()
+        };
+
+        assert_eq!(probe_mp4_codec(&make_file(b"alac")), AudioCodec::ALAC);
+        assert_eq!(probe_mp4_codec(&make_file(b"mp4a")), AudioCodec::AAC);
+        assert_eq!(probe_mp4_codec(&make_file(b"fLaC")), AudioCodec::FLAC);
+
+        // `moov` after a large `mdat` that was not buffered
+        let truncated = [
+            mp4_box(b"ftyp", b"M4A \0\0\0\0"),
+            0x1000u32.to_be_bytes().to_vec(),
+        ]
+        .concat();
+        assert_eq!(probe_mp4_codec(&truncated), AudioCodec::UnknownCodec);
+    }
+
+    #[test]
+    fn test_probe_riff() {
+        let make_file = |fmt: &[u8]| {
+            let mut file = b"RIFF\0\0\0\0WAVE".to_vec();
+            file.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+            file.extend_from_slice(b"fmt ");
+            file.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+            file.extend_from_slice(fmt);
+            file
+        };
+
+        let pcm = [&[1u8, 0, 2, 0][..], &[0; 12]].concat();
+        assert_eq!(probe_riff_codec(&make_file(&pcm)), AudioCodec::PCM);
+
+        let float = [&[3u8, 0, 2, 0][..], &[0; 12]].concat();
+        assert_eq!(probe_riff_codec(&make_file(&float)), AudioCodec::PCMFloat);
+
+        let mut extensible = vec![0xFE, 0xFF, 2, 0];
+        extensible.resize(24, 0);
+        extensible.extend_from_slice(&[3, 0, 0, 0, 0, 0, 0x10, 0]);
+        extensible.resize(40, 0);
+        assert_eq!(
+            probe_riff_codec(&make_file(&extensible)),
+            AudioCodec::PCMFloat
+        );
+
+        let other = [&[0x61u8, 0x00, 2, 0][..], &[0; 12]].concat();
+        assert_eq!(
+            probe_riff_codec(&make_file(&other)),
+            AudioCodec::WaveFormat(0x61)
+        );
+    }
+}
@@ -1,6 +1,11 @@
 use crate::utils::array_ext::ByteSliceExt;
 
-use super::{magic, metadata::get_audio_header_metadata_size, AudioType};
+use super::{
+    codec::{probe_mp4_codec, probe_ogg_codec, probe_riff_codec},
+    magic,
+    metadata::get_audio_header_metadata_size,
+    AudioCodec, AudioFormat, AudioType,
+};
 
 fn is_mp3(magic: u32) -> bool {
     // Framesync, should have first 11-bits set to 1.
@@ -17,6 +22,29 @@ fn is_aac(magic: u32) -> bool {
     (magic & AAC_MAGIC_AND_MASK) == AAC_MAGIC_EXPECTED
 }
 
+fn is_ac3(magic: u32) -> bool {
+    (magic >> 16) as u16 == magic::AC3_SYNC
+}
+
+/// Matroska and WebM share the EBML magic; tell them apart by the DocType.
+fn detect_ebml_doc_type(buf: &[u8]) -> AudioType {
+    let is_webm = buf
+        .windows(magic::EBML_DOC_TYPE.len())
+        .position(|w| w == magic::EBML_DOC_TYPE)
+        .and_then(|i| {
+            // DocType size is a one byte vint (0x80 | len) in practice.
+            let size = *buf.get(i + 2)? as usize & 0x7F;
+            buf.get(i + 3..i + 3 + size)
+        })
+        .is_some_and(|doc_type| doc_type == magic::EBML_DOC_TYPE_WEBM);
+
+    if is_webm {
+        AudioType::WEBM
+    } else {
+        AudioType::MKA
+    }
+}
+
 pub fn detect_audio_type<T: AsRef<[u8]>>(header: T) -> AudioType {
     let mut buf = header.as_ref();
 
@@ -56,11 +84,41 @@ pub fn detect_audio_type<T: AsRef<[u8]>>(header: T) -> AudioType {
             magic::APE_MAGIC_MAC => {
                 return AudioType::APE;
             }
+            magic::DSD_ => {
+                return AudioType::DSF;
+            }
+            magic::FORM => {
+                // AIFF-C is treated as AIFF, even though it may be compressed.
+                if let magic::FORM_AIFF | magic::FORM_AIFC = buf.read_be::<u32>(8) {
+                    return AudioType::AIFF;
+                }
+            }
+            magic::wvpk => {
+                return AudioType::WV;
+            }
+            magic::TTA1 => {
+                return AudioType::TTA;
+            }
+            magic::EBML => {
+                return detect_ebml_doc_type(buf);
+            }
+            magic::MPCK => {
+                return AudioType::MPC;
+            }
+            magic::DTS_BE | magic::DTS_LE | magic::DTS_14_BE | magic::DTS_14_LE => {
+                return AudioType::DTS;
+            }
             _ => {}
         };
 
+        if magic >> 8 == magic::MP_plus {
+            return AudioType::MPC;
+        }
+
         // Detect type by its frame header
-        if is_aac(magic) {
+        if is_ac3(magic) {
+            return AudioType::AC3;
+        } else if is_aac(magic) {
             return AudioType::AAC;
         } else if is_mp3(magic) {
             return AudioType::MP3;
@@ -97,3 +155,85 @@ pub fn detect_audio_type<T: AsRef<[u8]>>(header: T) -> AudioType {
 
     AudioType::UnknownType
 }
+
+/// Like `detect_audio_type`, but also looks for the codec inside Ogg, MP4 and RIFF containers.
+pub fn detect_audio_format<T: AsRef<[u8]>>(header: T) -> AudioFormat {
+    let header = header.as_ref();
+    let container = detect_audio_type(header);
+    let buf = header
+        .get(get_audio_header_metadata_size(header)..)
+        .unwrap_or_default();
+
+    let codec = match container {
+        AudioType::OGG => probe_ogg_codec(buf),
+        AudioType::MP4 | AudioType::M4A | AudioType::M4B => probe_mp4_codec(buf),
+        AudioType::WAV => probe_riff_codec(buf),
+        AudioType::FLAC => AudioCodec::FLAC,
+        AudioType::MP3 => AudioCodec::MP3,
+        AudioType::AAC => AudioCodec::AAC,
+        AudioType::AC3 => AudioCodec::AC3,
+        _ => AudioCodec::UnknownCodec,
+    };
+
+    AudioFormat { container, codec }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{detect_audio_format, detect_audio_type};
+    use crate::utils::audio::{AudioCodec, AudioExtensionName, AudioType};
+
+    fn detect_ext(header: &[u8]) -> String {
+        let mut buf = header.to_vec();
+        buf.resize(usize::max(buf.len(), 64), 0);
+        detect_audio_type(buf).to_audio_ext().into()
+    }
+
+    #[test]
+    fn test_detect_containers() {
+        assert_eq!(detect_ext(b"DSD \x1c\0\0\0\0\0\0\0"), "dsf");
+        assert_eq!(detect_ext(b"FORM\0\0\x10\0AIFFCOMM"), "aiff");
+        assert_eq!(detect_ext(b"FORM\0\0\x10\0AIFCFVER"), "aiff");
+        assert_eq!(detect_ext(b"FORM\0\0\x10\0ILBMBMHD"), "bin");
+        assert_eq!(detect_ext(b"wvpk\x20\0\0\0\x10\x04"), "wv");
+        assert_eq!(detect_ext(b"TTA1\x01\0\x02\0\x10\0"), "tta");
+        assert_eq!(detect_ext(b"MPCKSH\x10"), "mpc");
+        assert_eq!(detect_ext(b"MP+\x17"), "mpc");
+    }
+
+    #[test]
+    fn test_detect_ebml() {
+        let webm = b"\x1A\x45\xDF\xA3\x9F\x42\x86\x81\x01\x42\x82\x84webm\x42\x87\x81\x04";
+        let mka = b"\x1A\x45\xDF\xA3\xA3\x42\x86\x81\x01\x42\x82\x88matroska\x42\x87\x81\x04";
+        assert_eq!(detect_ext(webm), "webm");
+        assert_eq!(detect_ext(mka), "mka");
+    }
+
+    #[test]
+    fn test_detect_raw_streams() {
+        assert_eq!(detect_ext(b"\x0B\x77\x12\x34\x04\x40"), "ac3");
+        assert_eq!(detect_ext(b"\x7F\xFE\x80\x01\xFC\x3C"), "dts");
+        assert_eq!(detect_ext(b"\xFE\x7F\x01\x80\x3C\xFC"), "dts");
+        assert_eq!(detect_ext(b"\x1F\xFF\xE8\x00\x07\xF0"), "dts");
+        assert_eq!(detect_ext(b"\xFF\xFB\x90\x64"), "mp3");
+        assert_eq!(detect_ext(b"\xFF\xF1\x50\x80"), "aac");
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let mut opus = b"OggS\x00\x02".to_vec();
+        opus.resize(26, 0);
+        opus.extend_from_slice(&[1, 19]);
+        opus.extend_from_slice(b"OpusHead\x01\x02\x38\x01\x80\xBB\x00\x00\x00\x00\x00");
+
+        let format = detect_audio_format(&opus);
+        assert_eq!(format.container, AudioType::OGG);
+        assert_eq!(format.codec, AudioCodec::Opus);
+        assert_eq!(format.to_audio_ext(), "opus");
+
+        let format = detect_audio_format(b"fLaC\0\0\0\x22\x12\0\x12\0\0\0\0\0");
+        assert_eq!(format.codec, AudioCodec::FLAC);
+        assert!(format.codec.is_lossless());
+        assert_eq!(format.to_audio_ext(), "flac");
+    }
+}
@@ -8,6 +8,24 @@ mod magic {
     pub const wma_u32_hdr: u32 = u32::from_be_bytes(*b"\x30\x26\xB2\x75"); // Windows WMA/WMV/ASF
     pub const RIFF: u32 = u32::from_be_bytes(*b"RIFF"); // Waveform Audio File Format (WAV)
     pub const APE_MAGIC_MAC: u32 = u32::from_be_bytes(*b"MAC "); // Monkey's Audio (APE; u8 "MAC ")
+    pub const DSD_: u32 = u32::from_be_bytes(*b"DSD "); // DSD Stream File (DSF)
+    pub const FORM: u32 = u32::from_be_bytes(*b"FORM"); // Interchange File Format (AIFF/AIFC)
+    pub const wvpk: u32 = u32::from_be_bytes(*b"wvpk"); // WavPack
+    pub const TTA1: u32 = u32::from_be_bytes(*b"TTA1"); // True Audio (TTA)
+    pub const EBML: u32 = u32::from_be_bytes(*b"\x1A\x45\xDF\xA3"); // Matroska / WebM (EBML)
+    pub const MPCK: u32 = u32::from_be_bytes(*b"MPCK"); // Musepack SV8
+    pub const MP_plus: u32 = u32::from_be_bytes(*b"\x00MP+"); // Musepack SV7 (u24 "MP+")
+    pub const DTS_BE: u32 = u32::from_be_bytes(*b"\x7F\xFE\x80\x01"); // DTS Coherent Acoustics, 16-bit BE
+    pub const DTS_LE: u32 = u32::from_be_bytes(*b"\xFE\x7F\x01\x80"); // DTS Coherent Acoustics, 16-bit LE
+    pub const DTS_14_BE: u32 = u32::from_be_bytes(*b"\x1F\xFF\xE8\x00"); // DTS Coherent Acoustics, 14-bit BE
+    pub const DTS_14_LE: u32 = u32::from_be_bytes(*b"\xFF\x1F\x00\xE8"); // DTS Coherent Acoustics, 14-bit LE
+    pub const AC3_SYNC: u16 = 0x0B77; // Dolby Digital (AC-3 / E-AC-3), u16 syncword
+
+    pub const FORM_AIFF: u32 = u32::from_be_bytes(*b"AIFF"); // AIFF, uncompressed
+    pub const FORM_AIFC: u32 = u32::from_be_bytes(*b"AIFC"); // AIFF-C, possibly compressed
+
+    pub const EBML_DOC_TYPE: &[u8; 2] = b"\x42\x82"; // EBML DocType element id
+    pub const EBML_DOC_TYPE_WEBM: &[u8] = b"webm";
 
     pub const ftyp_MSNV: u32 = u32::from_be_bytes(*b"MSNV"); // MPEG-4 (.MP4) for SonyPSP
     pub const ftyp_NDAS: u32 = u32::from_be_bytes(*b"NDAS"); // Nero Digital AAC Audio
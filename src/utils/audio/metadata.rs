@@ -1,7 +1,9 @@
+use std::ops::Range;
+
 use crate::utils::array_ext::ByteSliceExt;
 
 #[inline]
-fn parse_id3_sync_safe_int(buf: &[u8]) -> u32 {
+pub(super) fn parse_id3_sync_safe_int(buf: &[u8]) -> u32 {
     let raw = buf.read_be::<u32>(0);
 
     ((raw & 0x7F00_0000) >> 3)
@@ -11,76 +13,516 @@ fn parse_id3_sync_safe_int(buf: &[u8]) -> u32 {
 }
 
 #[inline]
-fn get_id3_header_size(magic: u32, buf: &[u8]) -> usize {
-    if buf.len() < 10 {
-        return 0;
+pub(super) fn make_id3_sync_safe_int(value: u32) -> u32 {
+    ((value << 3) & 0x7F00_0000)
+        | ((value << 2) & 0x007F_0000)
+        | ((value << 1) & 0x0000_7F00)
+        | (value & 0x0000_007F)
+}
+
+const ID3V2_HEADER_SIZE: usize = 10;
+const ID3V2_FLAG_FOOTER: u8 = 0x10;
+const ID3V1_TAG_SIZE: usize = 128;
+const APE_V2_HEADER_SIZE: usize = 32;
+const APE_V2_FLAG_HAS_HEADER: u32 = 0x8000_0000;
+const APE_V2_MAGIC: &[u8; 8] = b"APETAGEX"; // cspell:disable-line
+const LYRICS3_V2_END_MAGIC: &[u8; 9] = b"LYRICS200";
+const LYRICS3_V2_BEGIN_MAGIC: &[u8; 11] = b"LYRICSBEGIN";
+const LYRICS3_V2_SIZE_LEN: usize = 6;
+
+/// ID3v2 tag size, from its 10 byte header (`ID3`) or footer (`3DI`).
+#[inline]
+pub(crate) fn get_id3v2_size(header: &[u8], magic: &[u8; 3]) -> Option<usize> {
+    // 'ID3' u8(ver_major) u8(ver_minor) u8(flags) u32_sync_safe(inner_tag_size)
+    //   byte[inner_tag_size] id3v2 data
+    //   [ '3DI' ... footer, same layout as the header ]
+    if header.len() < ID3V2_HEADER_SIZE || &header[..3] != magic {
+        return None;
     }
 
-    // ID3v1 and ID3v1.1: flat 128 bytes
-    const ID3V1_MASKS: u32 = 0xFFFFFF00; // Select first 3 bytes
-    const ID3V1_VALUE: u32 = u32::from_be_bytes(*b"TAG\x00");
-    if (magic & ID3V1_MASKS) == ID3V1_VALUE {
-        return 128;
+    let inner_tag_size = parse_id3_sync_safe_int(&header[6..10]) as usize;
+    let footer_size = if header[5] & ID3V2_FLAG_FOOTER != 0 {
+        ID3V2_HEADER_SIZE
+    } else {
+        0
+    };
+
+    Some(ID3V2_HEADER_SIZE + inner_tag_size + footer_size)
+}
+
+/// APEv2 `(tag_size, flags)` from its 32 byte header or footer;
+///   `tag_size` covers the items and the footer, but not the header.
+#[inline]
+fn parse_ape_v2_header(buf: &[u8]) -> Option<(usize, u32)> {
+    if buf.len() < APE_V2_HEADER_SIZE || !buf.starts_with(APE_V2_MAGIC) {
+        return None;
     }
 
-    const ID3V2_MASKS: u32 = 0xFFFFFF00; // Select first 3 bytes
-    const ID3V2_VALUE: u32 = u32::from_be_bytes(*b"ID3\x00");
-    if (magic & ID3V2_MASKS) != ID3V2_VALUE {
-        return 0;
+    // `tag_size` includes the footer, anything smaller is bogus.
+    let tag_size = buf.read_le::<u32>(0x0c) as usize;
+    if tag_size < APE_V2_HEADER_SIZE {
+        return None;
     }
 
-    // file = 'ID3'
-    //        u8(ver_major) u8(ver_minor)
-    //        u8(flags)
-    //        u32(inner_tag_size)
-    //        byte[inner_tag_size] id3v2 data
-    //        byte[*] original_file_content
-    let id3v2_inner_tag_size = parse_id3_sync_safe_int(&buf[6..10]) as usize;
-    if id3v2_inner_tag_size == 0 {
-        return 0;
+    Some((tag_size, buf.read_le::<u32>(0x14)))
+}
+
+/// Size of the leading ID3v2 or APEv2 tag at the start of `buf`.
+#[inline]
+fn get_leading_tag_size(buf: &[u8]) -> Option<usize> {
+    get_id3v2_size(buf, b"ID3").or_else(|| {
+        // It's possible to have APEv2 header at the beginning of a file, though rare.
+        parse_ape_v2_header(buf).map(|(tag_size, _)| APE_V2_HEADER_SIZE + tag_size)
+    })
+}
+
+/// Total size of the tags (ID3v2, APEv2, possibly chained) before the audio data.
+///
+/// Tags are followed only as long as their header is within `buf`,
+///   so the result may exceed `buf.len()`.
+pub fn get_audio_header_metadata_size(buf: &[u8]) -> usize {
+    let mut offset = 0;
+
+    while let Some(tag_size) = buf.get(offset..).and_then(get_leading_tag_size) {
+        if tag_size == 0 {
+            break;
+        }
+        offset += tag_size;
     }
 
-    10 + id3v2_inner_tag_size
+    offset
 }
 
+/// Size of the trailing tag at the end of `buf`, if any.
 #[inline]
-fn get_ape_v2_full_size(magic1: u32, buf: &[u8]) -> usize {
-    let magic2: u32 = buf.read_be::<u32>(4);
-    // cspell:disable
-    const APE_V2_MAGIC1: u32 = u32::from_be_bytes(*b"APET");
-    const APE_V2_MAGIC2: u32 = u32::from_be_bytes(*b"AGEX");
-    // cspell:enable
+fn get_trailing_tag_size(buf: &[u8]) -> Option<usize> {
+    let len = buf.len();
 
-    if magic1 != APE_V2_MAGIC1 || magic2 != APE_V2_MAGIC2 {
-        return 0;
+    // APEv2 footer, optionally preceded by a header.
+    if let Some((tag_size, flags)) = buf
+        .get(len.saturating_sub(APE_V2_HEADER_SIZE)..)
+        .and_then(parse_ape_v2_header)
+    {
+        let has_header = flags & APE_V2_FLAG_HAS_HEADER != 0;
+        return Some(tag_size + if has_header { APE_V2_HEADER_SIZE } else { 0 });
     }
 
-    // Tag size in bytes including footer and all tag items excluding the header.
-    const APE_V2_HEADER_SIZE: usize = 32;
-    let tag_size = buf.read_le::<u32>(0x0c) as usize;
+    // Lyrics3v2: "LYRICSBEGIN" ... char[6](size) "LYRICS200"; `size` excludes the last 15 bytes.
+    if buf.ends_with(LYRICS3_V2_END_MAGIC) {
+        let size_end = len - LYRICS3_V2_END_MAGIC.len();
+        let size = buf
+            .get(size_end.saturating_sub(LYRICS3_V2_SIZE_LEN)..size_end)
+            .and_then(|size| std::str::from_utf8(size).ok())
+            .and_then(|size| size.parse::<usize>().ok());
+        if let Some(size) = size {
+            let total = size + LYRICS3_V2_SIZE_LEN + LYRICS3_V2_END_MAGIC.len();
+            let is_valid = len
+                .checked_sub(total)
+                .and_then(|begin| buf.get(begin..))
+                .is_some_and(|tag| tag.starts_with(LYRICS3_V2_BEGIN_MAGIC));
+            if is_valid {
+                return Some(total);
+            }
+        }
+    }
+
+    // ID3v2 appended to the end of the file, with a footer.
+    if let Some(size) = buf
+        .get(len.saturating_sub(ID3V2_HEADER_SIZE)..)
+        .and_then(|footer| get_id3v2_size(footer, b"3DI"))
+    {
+        return Some(size);
+    }
+
+    // ID3v1 and ID3v1.1: flat 128 bytes
+    if len >= ID3V1_TAG_SIZE && buf[len - ID3V1_TAG_SIZE..].starts_with(b"TAG") {
+        return Some(ID3V1_TAG_SIZE);
+    }
 
-    APE_V2_HEADER_SIZE + tag_size
+    None
+}
+
+/// Total size of the tags (ID3v1, Lyrics3v2, APEv2, ID3v2 with footer)
+///   at the end of `buf`, which should be the end of the file.
+pub fn get_audio_trailer_metadata_size(buf: &[u8]) -> usize {
+    let mut size = 0;
+
+    while let Some(tag_size) = get_trailing_tag_size(&buf[..buf.len() - size]) {
+        if tag_size == 0 || tag_size > buf.len() - size {
+            break;
+        }
+        size += tag_size;
+    }
+
+    size
+}
+
+/// Range of the audio data in a complete file, without leading or trailing tags.
+pub fn get_audio_payload_range(file: &[u8]) -> Range<usize> {
+    let begin = usize::min(get_audio_header_metadata_size(file), file.len());
+    let end = file.len() - get_audio_trailer_metadata_size(&file[begin..]);
+    begin..end
+}
+
+/// Vendor string and the raw `NAME=value` entries of a Vorbis comment.
+pub(super) fn parse_vorbis_comment(data: &[u8]) -> Option<(Vec<u8>, Vec<Vec<u8>>)> {
+    let read_u32 = |offset: usize| -> Option<usize> {
+        data.get(offset..offset + 4)
+            .map(|v| v.read_le::<u32>(0) as usize)
+    };
+
+    let vendor_len = read_u32(0)?;
+    let vendor = data.get(4..4 + vendor_len)?.to_vec();
+    let mut offset = 4 + vendor_len;
+    let count = read_u32(offset)?;
+    offset += 4;
+
+    let mut comments = vec![];
+    for _ in 0..count {
+        let len = read_u32(offset)?;
+        comments.push(data.get(offset + 4..offset + 4 + len)?.to_vec());
+        offset += 4 + len;
+    }
+
+    Some((vendor, comments))
 }
 
 #[inline]
-pub fn get_audio_header_metadata_size(buf: &[u8]) -> usize {
-    // Not enough bytes to detect
+fn decode_id3_text(encoding: u8, data: &[u8]) -> String {
+    let decode_utf16 = |data: &[u8], big_endian: bool| {
+        let units: Vec<u16> = data
+            .chunks_exact(2)
+            .map(|v| match big_endian {
+                true => u16::from_be_bytes([v[0], v[1]]),
+                false => u16::from_le_bytes([v[0], v[1]]),
+            })
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    let text = match encoding {
+        // UTF-16 with BOM
+        1 => match data {
+            [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, true),
+            [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, false),
+            _ => decode_utf16(data, false),
+        },
+        // UTF-16BE
+        2 => decode_utf16(data, true),
+        // UTF-8
+        3 => String::from_utf8_lossy(data).into_owned(),
+        // ISO-8859-1
+        _ => data.iter().map(|&v| v as char).collect(),
+    };
+
+    text.trim_end_matches('\0').to_string()
+}
+
+/// Split an encoded ID3 string at its terminator.
+#[inline]
+fn split_id3_text(encoding: u8, data: &[u8]) -> (&[u8], &[u8]) {
+    let position = match encoding {
+        1 | 2 => data
+            .chunks_exact(2)
+            .position(|v| v == [0, 0])
+            .map(|i| (i * 2, i * 2 + 2)),
+        _ => data.iter().position(|&v| v == 0).map(|i| (i, i + 1)),
+    };
+
+    match position {
+        Some((end, next)) => (&data[..end], &data[next..]),
+        None => (data, &[]),
+    }
+}
+
+/// `(frame_id, payload)` of each frame in an ID3v2 tag at the beginning of `buf`.
+/// Frames not fully within `buf` are ignored.
+fn read_id3v2_frames(buf: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut result = vec![];
+    if get_id3v2_size(buf, b"ID3").is_none() || buf[3] < 2 {
+        return result;
+    }
+
+    let version = buf[3];
+    let flags = buf[5];
+    // Without the footer, if any.
+    let body_end = ID3V2_HEADER_SIZE + parse_id3_sync_safe_int(&buf[6..10]) as usize;
+    let body = &buf[ID3V2_HEADER_SIZE..usize::min(body_end, buf.len())];
+
+    // Tag level unsynchronisation (v2.2/2.3): `FF 00` => `FF`
+    let body: Vec<u8> = if flags & 0x80 != 0 && version < 4 {
+        let mut result = Vec::with_capacity(body.len());
+        for (i, &v) in body.iter().enumerate() {
+            if !(v == 0 && i > 0 && body[i - 1] == 0xFF) {
+                result.push(v);
+            }
+        }
+        result
+    } else {
+        body.to_vec()
+    };
+
+    // Extended header
+    let mut offset = 0;
+    if flags & 0x40 != 0 && version >= 3 && body.len() >= 4 {
+        offset = match version {
+            3 => 4 + body.read_be::<u32>(0) as usize,
+            _ => parse_id3_sync_safe_int(&body) as usize,
+        };
+    }
+
+    // v2.2: id(3) size(3);  v2.3/v2.4: id(4) size(4) flags(2)
+    let (id_len, header_len) = match version {
+        2 => (3, 6),
+        _ => (4, 10),
+    };
+
+    while offset + header_len <= body.len() && body[offset] != 0 {
+        let frame_size = match version {
+            2 => (body.read_be::<u32>(offset + 2) & 0x00FF_FFFF) as usize,
+            3 => body.read_be::<u32>(offset + 4) as usize,
+            _ => parse_id3_sync_safe_int(&body[offset + 4..]) as usize,
+        };
+
+        let frame_start = offset + header_len;
+        let frame_end = frame_start + frame_size;
+        if frame_end > body.len() {
+            break;
+        }
+
+        result.push((
+            body[offset..offset + id_len].to_vec(),
+            body[frame_start..frame_end].to_vec(),
+        ));
+        offset = frame_end;
+    }
+
+    result
+}
+
+/// Text of the `COMM` frames in an ID3v2 tag at the beginning of `buf`.
+fn read_id3v2_comments(buf: &[u8]) -> Vec<String> {
+    read_id3v2_frames(buf)
+        .into_iter()
+        .filter(|(id, payload)| (id == b"COMM" || id == b"COM") && payload.len() > 4)
+        .map(|(_, payload)| {
+            // encoding(1) language(3) description(str) text(str)
+            let encoding = payload[0];
+            let (_, text) = split_id3_text(encoding, &payload[4..]);
+            decode_id3_text(encoding, text)
+        })
+        .collect()
+}
+
+/// Text of the first text frame (e.g. `TIT2`) with the given id,
+///   in an ID3v2 tag at the beginning of `buf`.
+pub fn find_id3v2_text_frame(buf: &[u8], frame_id: &str) -> Option<String> {
+    if buf.len() < 10 {
+        return None;
+    }
+
+    read_id3v2_frames(buf)
+        .into_iter()
+        .find(|(id, payload)| id == frame_id.as_bytes() && !payload.is_empty())
+        .map(|(_, payload)| {
+            // encoding(1) text(str)
+            let encoding = payload[0];
+            let (text, _) = split_id3_text(encoding, &payload[1..]);
+            decode_id3_text(encoding, text)
+        })
+}
+
+/// Values of `DESCRIPTION` and `COMMENT` fields in the Vorbis comment of a FLAC stream.
+/// Blocks not fully within `buf` are ignored.
+fn read_flac_comments(buf: &[u8]) -> Vec<String> {
+    const FLAC_VORBIS_COMMENT: u32 = 4;
+    const COMMENT_FIELDS: &[&[u8]] = &[b"DESCRIPTION=", b"COMMENT="];
+
+    let mut result = vec![];
+    if !buf.starts_with(b"fLaC") {
+        return result;
+    }
+
+    let mut offset = 4;
+    while offset + 4 <= buf.len() {
+        let header = buf.read_be::<u32>(offset);
+        let block_type = (header >> 24) & 0x7F;
+        let block_start = offset + 4;
+        let block_end = block_start + (header & 0x00FF_FFFF) as usize;
+        if block_end > buf.len() {
+            break;
+        }
+
+        if block_type == FLAC_VORBIS_COMMENT {
+            let (_, comments) =
+                parse_vorbis_comment(&buf[block_start..block_end]).unwrap_or_default();
+            for comment in comments {
+                let is_comment_field = COMMENT_FIELDS.iter().any(|name| {
+                    comment.len() >= name.len() && comment[..name.len()].eq_ignore_ascii_case(name)
+                });
+                if let (true, Some(eq)) =
+                    (is_comment_field, comment.iter().position(|&v| v == b'='))
+                {
+                    result.push(String::from_utf8_lossy(&comment[eq + 1..]).into_owned());
+                }
+            }
+        }
+
+        if header & 0x8000_0000 != 0 {
+            break;
+        }
+        offset = block_end;
+    }
+
+    result
+}
+
+/// Comments in the header of an audio file:
+///   ID3v2 `COMM` frames, and FLAC `DESCRIPTION`/`COMMENT` fields.
+pub fn find_audio_comments(buf: &[u8]) -> Vec<String> {
     if buf.len() < 10 {
-        return 0;
+        return vec![];
     }
 
-    let magic = buf.read_be::<u32>(0);
+    let mut result = read_id3v2_comments(buf);
 
-    let id3_meta_size = get_id3_header_size(magic, buf);
-    if id3_meta_size > 0 {
-        return id3_meta_size;
+    // FLAC can be prefixed with an ID3v2 tag.
+    let flac_offset = get_audio_header_metadata_size(buf);
+    if flac_offset < buf.len() {
+        result.extend(read_flac_comments(&buf[flac_offset..]));
     }
 
-    // It's possible to have APEv2 header at the beginning of a file, though rare.
-    let ape_meta_size = get_ape_v2_full_size(magic, buf);
-    if ape_meta_size > 0 {
-        return ape_meta_size;
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        find_audio_comments, get_audio_header_metadata_size, get_audio_payload_range,
+        get_audio_trailer_metadata_size,
+    };
+
+    fn make_ape_v2(items: &[u8], is_footer: bool, has_header: bool) -> Vec<u8> {
+        let flags = if has_header { 0x8000_0000u32 } else { 0 };
+        let flags = if is_footer {
+            flags
+        } else {
+            flags | 0x2000_0000
+        };
+        [
+            &b"APETAGEX"[..],
+            &2000u32.to_le_bytes(),
+            &(items.len() as u32 + 32).to_le_bytes(),
+            &1u32.to_le_bytes(),
+            &flags.to_le_bytes(),
+            &[0u8; 8],
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_header_metadata_size() {
+        // ID3v2.4 with footer flag: 10 + 0x81 + 10
+        let mut file = b"ID3\x04\x00\x10\x00\x00\x01\x01".to_vec();
+        file.resize(file.len() + 0x81, 0);
+        file.extend_from_slice(b"3DI\x04\x00\x10\x00\x00\x01\x01");
+        assert_eq!(get_audio_header_metadata_size(&file), 0x95);
+
+        // Chained APEv2 (header + items + footer)
+        let items = [0x55u8; 20];
+        file.extend(make_ape_v2(&items, false, true));
+        file.extend_from_slice(&items);
+        file.extend(make_ape_v2(&items, true, true));
+        file.extend_from_slice(b"fLaC\0\0\0\x22");
+        assert_eq!(get_audio_header_metadata_size(&file), 0x95 + 32 + 20 + 32);
+
+        // Only the ID3v2 header is buffered: size known, nothing after it.
+        assert_eq!(get_audio_header_metadata_size(&file[..10]), 0x95);
+
+        // ID3v1 belongs at the end of a file.
+        let mut id3v1 = b"TAG".to_vec();
+        id3v1.resize(128, 0x20);
+        assert_eq!(get_audio_header_metadata_size(&id3v1), 0);
+    }
+
+    #[test]
+    fn test_trailer_metadata_size() {
+        let audio = b"\xFF\xFB\x90\x64 audio data";
+        let items = [0x55u8; 20];
+
+        let mut lyrics3 = b"LYRICSBEGININD00002".to_vec();
+        lyrics3.extend_from_slice(format!("{:06}LYRICS200", lyrics3.len()).as_bytes());
+
+        let mut id3v1 = b"TAG".to_vec();
+        id3v1.resize(128, 0x20);
+
+        let mut file = b"ID3\x03\x00\x00\x00\x00\x00\x00".to_vec();
+        file.extend_from_slice(audio);
+        file.extend_from_slice(&items);
+        file.extend(make_ape_v2(&items, true, false));
+        file.extend_from_slice(&lyrics3);
+        file.extend_from_slice(&id3v1);
+
+        let trailer_size = 20 + 32 + lyrics3.len() + 128;
+        assert_eq!(get_audio_trailer_metadata_size(&file), trailer_size);
+        assert_eq!(get_audio_payload_range(&file), 10..10 + audio.len());
+        assert_eq!(get_audio_trailer_metadata_size(audio), 0);
+
+        // Appended ID3v2 tag with footer
+        let mut file = audio.to_vec();
+        file.extend_from_slice(b"ID3\x04\x00\x10\x00\x00\x00\x02ab");
+        file.extend_from_slice(b"3DI\x04\x00\x10\x00\x00\x00\x02");
+        assert_eq!(get_audio_payload_range(&file), 0..audio.len());
     }
 
-    0
+    #[test]
+    fn test_zero_size_ape_v2_footer() {
+        // The declared size would not even cover the footer itself.
+        let mut footer = make_ape_v2(&[], true, false);
+        footer[0x0c..0x10].copy_from_slice(&0u32.to_le_bytes());
+
+        let mut file = vec![0x55u8; 32];
+        file.extend_from_slice(&footer);
+        assert_eq!(get_audio_trailer_metadata_size(&file), 0);
+        assert_eq!(get_audio_payload_range(&file), 0..64);
+
+        footer[0x14..0x18].copy_from_slice(&0x2000_0000u32.to_le_bytes());
+        assert_eq!(get_audio_header_metadata_size(&footer), 0);
+    }
+
+    #[test]
+    fn test_find_id3v2_comments() {
+        let mut comment = vec![0x01u8];
+        comment.extend_from_slice(b"eng");
+        comment.extend_from_slice(&[0xFF, 0xFE, 0x00, 0x00]); // empty description
+        comment.extend_from_slice(&[0xFF, 0xFE, b'H', 0, b'i', 0, 0x1F, 0x9A]); // "Hi\u{9a1f}"
+
+        let mut tag = Vec::from(&b"ID3\x03\x00\x00"[..]);
+        tag.extend_from_slice(&(comment.len() as u32 + 10).to_be_bytes());
+        tag.extend_from_slice(b"COMM");
+        tag.extend_from_slice(&(comment.len() as u32).to_be_bytes());
+        tag.extend_from_slice(&[0, 0]);
+        tag.extend_from_slice(&comment);
+
+        assert_eq!(find_audio_comments(&tag), vec!["Hi\u{9a1f}".to_string()]);
+    }
+
+    #[test]
+    fn test_find_flac_comments() {
+        let comments = [&b"TITLE=Parakeet"[..], b"description=163 key"];
+        let mut vorbis_comment = vec![0u8; 4]; // empty vendor
+        vorbis_comment.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            vorbis_comment.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            vorbis_comment.extend_from_slice(comment);
+        }
+
+        let mut flac = Vec::from(&b"fLaC"[..]);
+        flac.extend_from_slice(&[0x00, 0x00, 0x00, 0x22]);
+        flac.extend_from_slice(&[0x00; 0x22]);
+        flac.extend_from_slice(&(0x8400_0000u32 | vorbis_comment.len() as u32).to_be_bytes());
+        flac.extend_from_slice(&vorbis_comment);
+
+        assert_eq!(find_audio_comments(&flac), vec!["163 key".to_string()]);
+    }
 }
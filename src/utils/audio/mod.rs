@@ -13,12 +13,54 @@
 //   https://xiph.org/flac/format.html
 // - fytp:
 //   https://www.ftyps.com/
+// - DSF:
+//   https://dsd-guide.com/sites/default/files/white-papers/DSFFileFormatSpec_E.pdf
+// - AIFF / AIFF-C:
+//   http://www-mmsp.ece.mcgill.ca/Documents/AudioFormats/AIFF/AIFF.html
+// - WavPack:
+//   https://www.wavpack.com/WavPack5FileFormat.pdf
+// - Matroska / WebM (EBML):
+//   https://www.rfc-editor.org/rfc/rfc8794
+// - Musepack:
+//   https://wiki.hydrogenaud.io/index.php?title=Musepack
+// - ID3v1 / ID3v2:
+//   https://id3.org/ID3v1, https://id3.org/id3v2.4.0-structure
+// - APEv2:
+//   https://wiki.hydrogenaud.io/index.php?title=APEv2_specification
+// - Lyrics3v2:
+//   https://id3.org/Lyrics3v2
+// - Ogg codec headers:
+//   https://xiph.org/vorbis/doc/Vorbis_I_spec.html, https://www.rfc-editor.org/rfc/rfc7845
+// - MP4 sample entries:
+//   https://developer.apple.com/documentation/quicktime-file-format
+// - WAVE format tags:
+//   https://learn.microsoft.com/en-us/windows/win32/api/mmreg/ns-mmreg-waveformatex
+// - AC-3 / DTS:
+//   https://wiki.multimedia.cx/index.php/AC-3
+//   https://wiki.multimedia.cx/index.php/DTS
 
 mod audio_type;
+mod codec;
 mod detect;
 mod magic;
 mod metadata;
+mod tag_writer;
+mod type_sniffer;
 
 pub use audio_type::AudioExtensionName;
 pub use audio_type::AudioType;
+pub use audio_type::ParseAudioTypeError;
+pub use codec::AudioCodec;
+pub use codec::AudioFormat;
+pub use detect::detect_audio_format;
 pub use detect::detect_audio_type;
+pub use metadata::find_audio_comments;
+pub use metadata::find_id3v2_text_frame;
+pub use metadata::get_audio_header_metadata_size;
+pub use metadata::get_audio_payload_range;
+pub use metadata::get_audio_trailer_metadata_size;
+pub(crate) use metadata::get_id3v2_size;
+pub use tag_writer::AudioCover;
+pub use tag_writer::AudioTags;
+pub use tag_writer::TagWriter;
+pub use type_sniffer::AudioTypeSniffer;
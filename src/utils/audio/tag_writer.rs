@@ -0,0 +1,523 @@
+// References:
+// - ID3v2.3 / ID3v2.4:
+//   https://id3.org/id3v2.3.0
+//   https://id3.org/id3v2.4.0-structure
+// - FLAC metadata blocks:
+//   https://xiph.org/flac/format.html#metadata_block
+// - Vorbis comment:
+//   https://xiph.org/vorbis/doc/v-comment.html
+
+use crate::utils::image::ImageInfo;
+
+#[derive(Debug, Clone)]
+pub struct AudioCover {
+    pub data: Vec<u8>,
+    pub info: ImageInfo,
+}
+
+/// Tags to be written to the beginning of an audio stream.
+#[derive(Debug, Clone, Default)]
+pub struct AudioTags {
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album: String,
+    pub cover: Option<AudioCover>,
+}
+
+mod detail {
+    use super::{AudioCover, AudioTags};
+    use crate::utils::{
+        array_ext::ByteSliceExt,
+        audio::metadata::{make_id3_sync_safe_int, parse_id3_sync_safe_int, parse_vorbis_comment},
+    };
+
+    const ID3V2_HEADER_SIZE: usize = 10;
+    const ID3V2_WRITE_VERSION: u8 = 3;
+    const ID3V2_FLAG_UNSYNC: u8 = 0x80;
+    const ID3V2_FLAG_EXTENDED_HEADER: u8 = 0x40;
+    const ID3V2_FLAG_FOOTER: u8 = 0x10;
+    const ID3V2_FRAME_HEADER_SIZE: usize = 10;
+    const ID3V2_PICTURE_FRONT_COVER: u8 = 3;
+
+    // MPEG audio frame header: 11 sync bits, then version (2) and layer (2);
+    //   layer `00` is reserved for MP1/2/3, AAC ADTS uses it.
+    const MPEG_FRAME_SYNC_MASK: u16 = 0xFFE0;
+    const MPEG_FRAME_LAYER_MASK: u16 = 0x0006;
+
+    const FLAC_MAGIC: &[u8; 4] = b"fLaC";
+    const FLAC_BLOCK_HEADER_SIZE: usize = 4;
+    const FLAC_BLOCK_LAST_FLAG: u32 = 0x8000_0000;
+    const FLAC_BLOCK_MAX_SIZE: usize = 0x00FF_FFFF;
+    const FLAC_BLOCK_STREAMINFO: u8 = 0;
+    const FLAC_BLOCK_VORBIS_COMMENT: u8 = 4;
+    const FLAC_BLOCK_PICTURE: u8 = 6;
+    const FLAC_PICTURE_FRONT_COVER: u32 = 3;
+    const VORBIS_COMMENT_VENDOR: &[u8] = b"Parakeet";
+
+    #[inline]
+    fn encode_id3_text(version: u8, text: &str) -> Vec<u8> {
+        if text.is_ascii() {
+            // ISO-8859-1
+            [&[0u8], text.as_bytes()].concat()
+        } else if version >= 4 {
+            // UTF-8
+            [&[3u8], text.as_bytes()].concat()
+        } else {
+            // UTF-16 with BOM
+            let mut result = vec![1u8, 0xFF, 0xFE];
+            for v in text.encode_utf16() {
+                result.extend_from_slice(&v.to_le_bytes());
+            }
+            result
+        }
+    }
+
+    #[inline]
+    fn make_id3_frame(version: u8, id: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let size = payload.len() as u32;
+        let size = if version >= 4 {
+            make_id3_sync_safe_int(size)
+        } else {
+            size
+        };
+
+        [id, &size.to_be_bytes()[..], &[0u8, 0u8], payload].concat()
+    }
+
+    fn make_id3_cover_payload(cover: &AudioCover) -> Vec<u8> {
+        let mime = cover.info.image_type.mime_type().as_bytes();
+        [
+            &[0u8],                       // encoding: ISO-8859-1
+            mime,                         //
+            &[0u8],                       // mime terminator
+            &[ID3V2_PICTURE_FRONT_COVER], // picture type
+            &[0u8],                       // empty description
+            cover.data.as_slice(),        //
+        ]
+        .concat()
+    }
+
+    /// Frames for the given tags, skipping any frame id in `existing_ids`.
+    fn make_id3_frames(version: u8, tags: &AudioTags, existing_ids: &[[u8; 4]]) -> Vec<u8> {
+        let mut frames = vec![];
+        let mut add_frame = |id: &[u8; 4], payload: Vec<u8>| {
+            if !existing_ids.contains(id) {
+                frames.extend(make_id3_frame(version, id, &payload));
+            }
+        };
+
+        if !tags.title.is_empty() {
+            add_frame(b"TIT2", encode_id3_text(version, &tags.title));
+        }
+        if !tags.artists.is_empty() {
+            add_frame(b"TPE1", encode_id3_text(version, &tags.artists.join("/")));
+        }
+        if !tags.album.is_empty() {
+            add_frame(b"TALB", encode_id3_text(version, &tags.album));
+        }
+        if let Some(cover) = &tags.cover {
+            add_frame(b"APIC", make_id3_cover_payload(cover));
+        }
+
+        frames
+    }
+
+    fn make_id3_tag(version: u8, frames: &[u8]) -> Vec<u8> {
+        if frames.is_empty() {
+            return vec![];
+        }
+
+        let size = make_id3_sync_safe_int(frames.len() as u32);
+        [b"ID3", &[version, 0, 0][..], &size.to_be_bytes(), frames].concat()
+    }
+
+    /// Add missing frames to an existing ID3v2.3/2.4 tag.
+    /// Returns `None` if more data is required.
+    fn rewrite_id3v2(buf: &[u8], tags: &AudioTags) -> Option<(Vec<u8>, usize)> {
+        if buf.len() < ID3V2_HEADER_SIZE {
+            return None;
+        }
+
+        let version = buf[3];
+        let flags = buf[5];
+        let body_size = parse_id3_sync_safe_int(&buf[6..10]) as usize;
+        let tag_size = ID3V2_HEADER_SIZE + body_size;
+
+        // Tags we can't safely rewrite are kept as is.
+        let unsupported_flags = ID3V2_FLAG_UNSYNC | ID3V2_FLAG_EXTENDED_HEADER | ID3V2_FLAG_FOOTER;
+        if !(3..=4).contains(&version) || flags & unsupported_flags != 0 {
+            return Some((vec![], 0));
+        }
+
+        if buf.len() < tag_size {
+            return None;
+        }
+
+        let body = &buf[ID3V2_HEADER_SIZE..tag_size];
+        let mut existing_ids = vec![];
+        let mut frames_end = 0;
+        while frames_end + ID3V2_FRAME_HEADER_SIZE <= body.len() && body[frames_end] != 0 {
+            let mut id = [0u8; 4];
+            id.copy_from_slice(&body[frames_end..frames_end + 4]);
+            let frame_size = if version >= 4 {
+                parse_id3_sync_safe_int(&body[frames_end + 4..]) as usize
+            } else {
+                body.read_be::<u32>(frames_end + 4) as usize
+            };
+
+            let next = frames_end + ID3V2_FRAME_HEADER_SIZE + frame_size;
+            if next > body.len() {
+                return Some((vec![], 0));
+            }
+
+            existing_ids.push(id);
+            frames_end = next;
+        }
+
+        let new_frames = make_id3_frames(version, tags, &existing_ids);
+        let frames = [&body[..frames_end], &new_frames[..]].concat();
+        Some((make_id3_tag(version, &frames), tag_size))
+    }
+
+    #[inline]
+    fn make_flac_block(block_type: u8, is_last: bool, data: &[u8]) -> Vec<u8> {
+        let mut header = ((block_type as u32) << 24) | (data.len() as u32);
+        if is_last {
+            header |= FLAC_BLOCK_LAST_FLAG;
+        }
+        [&header.to_be_bytes()[..], data].concat()
+    }
+
+    fn make_vorbis_comment(vendor: &[u8], comments: &[Vec<u8>]) -> Vec<u8> {
+        let mut result = vec![];
+        result.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        result.extend_from_slice(vendor);
+        result.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            result.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            result.extend_from_slice(comment);
+        }
+        result
+    }
+
+    /// Add missing TITLE/ARTIST/ALBUM fields to the (possibly empty) comment list.
+    fn merge_vorbis_comments(comments: &mut Vec<Vec<u8>>, tags: &AudioTags) {
+        let has_field = |comments: &Vec<Vec<u8>>, name: &str| {
+            comments.iter().any(|comment| {
+                comment.len() > name.len()
+                    && comment[name.len()] == b'='
+                    && comment[..name.len()].eq_ignore_ascii_case(name.as_bytes())
+            })
+        };
+
+        let mut fields = vec![];
+        if !tags.title.is_empty() && !has_field(comments, "TITLE") {
+            fields.push(format!("TITLE={}", tags.title));
+        }
+        if !has_field(comments, "ARTIST") {
+            for artist in tags.artists.iter() {
+                fields.push(format!("ARTIST={}", artist));
+            }
+        }
+        if !tags.album.is_empty() && !has_field(comments, "ALBUM") {
+            fields.push(format!("ALBUM={}", tags.album));
+        }
+
+        comments.extend(fields.into_iter().map(String::into_bytes));
+    }
+
+    fn make_flac_picture(cover: &AudioCover) -> Vec<u8> {
+        let mime = cover.info.image_type.mime_type().as_bytes();
+        let mut result = vec![];
+        result.extend_from_slice(&FLAC_PICTURE_FRONT_COVER.to_be_bytes());
+        result.extend_from_slice(&(mime.len() as u32).to_be_bytes());
+        result.extend_from_slice(mime);
+        result.extend_from_slice(&0u32.to_be_bytes()); // description
+        result.extend_from_slice(&cover.info.width.to_be_bytes());
+        result.extend_from_slice(&cover.info.height.to_be_bytes());
+        result.extend_from_slice(&0u32.to_be_bytes()); // colour depth, unknown
+        result.extend_from_slice(&0u32.to_be_bytes()); // indexed colours, not indexed
+        result.extend_from_slice(&(cover.data.len() as u32).to_be_bytes());
+        result.extend_from_slice(&cover.data);
+        result
+    }
+
+    /// Rewrite the FLAC metadata block chain with our comments and picture.
+    /// Returns `None` if more data is required.
+    fn rewrite_flac(buf: &[u8], tags: &AudioTags) -> Option<(Vec<u8>, usize)> {
+        let mut blocks: Vec<(u8, Vec<u8>)> = vec![];
+        let mut offset = FLAC_MAGIC.len();
+        loop {
+            if buf.len() < offset + FLAC_BLOCK_HEADER_SIZE {
+                return None;
+            }
+
+            let header = buf.read_be::<u32>(offset);
+            let block_type = ((header >> 24) & 0x7F) as u8;
+            let block_len = (header & 0x00FF_FFFF) as usize;
+            let block_start = offset + FLAC_BLOCK_HEADER_SIZE;
+            if buf.len() < block_start + block_len {
+                return None;
+            }
+
+            blocks.push((
+                block_type,
+                buf[block_start..block_start + block_len].to_vec(),
+            ));
+            offset = block_start + block_len;
+            if header & FLAC_BLOCK_LAST_FLAG != 0 {
+                break;
+            }
+        }
+
+        if blocks.is_empty() || blocks[0].0 != FLAC_BLOCK_STREAMINFO {
+            return Some((vec![], 0));
+        }
+
+        // Merge into the existing comment block, or add one after STREAMINFO.
+        // A comment block we can't parse is kept as is, without our fields.
+        let comment_idx = blocks
+            .iter()
+            .position(|(block_type, _)| *block_type == FLAC_BLOCK_VORBIS_COMMENT);
+        let parsed_comment = match comment_idx {
+            Some(i) => parse_vorbis_comment(&blocks[i].1).map(|parsed| (Some(i), parsed)),
+            None => Some((None, (VORBIS_COMMENT_VENDOR.to_vec(), vec![]))),
+        };
+
+        let mut insert_idx = 1;
+        if let Some((comment_idx, (vendor, mut comments))) = parsed_comment {
+            merge_vorbis_comments(&mut comments, tags);
+            let comment_block = make_vorbis_comment(&vendor, &comments);
+
+            match comment_idx {
+                Some(i) if comment_block.len() <= FLAC_BLOCK_MAX_SIZE => {
+                    blocks[i].1 = comment_block;
+                }
+                None if comment_block.len() <= FLAC_BLOCK_MAX_SIZE => {
+                    blocks.insert(insert_idx, (FLAC_BLOCK_VORBIS_COMMENT, comment_block));
+                    insert_idx += 1;
+                }
+                _ => {}
+            }
+        }
+
+        let has_picture = blocks
+            .iter()
+            .any(|(block_type, _)| *block_type == FLAC_BLOCK_PICTURE);
+        if let (false, Some(cover)) = (has_picture, &tags.cover) {
+            let picture_block = make_flac_picture(cover);
+            if picture_block.len() <= FLAC_BLOCK_MAX_SIZE {
+                blocks.insert(insert_idx, (FLAC_BLOCK_PICTURE, picture_block));
+            }
+        }
+
+        let last_idx = blocks.len() - 1;
+        let mut result = Vec::from(&FLAC_MAGIC[..]);
+        for (i, (block_type, data)) in blocks.iter().enumerate() {
+            result.extend(make_flac_block(*block_type, i == last_idx, data));
+        }
+
+        Some((result, offset))
+    }
+
+    #[inline]
+    fn is_mpeg_audio_frame(header: u16) -> bool {
+        header & MPEG_FRAME_SYNC_MASK == MPEG_FRAME_SYNC_MASK && header & MPEG_FRAME_LAYER_MASK != 0
+    }
+
+    /// Returns the new header, and how many bytes of `buf` it replaces;
+    ///   or `None` if more data is required.
+    fn rewrite_header(buf: &[u8], tags: &AudioTags) -> Option<(Vec<u8>, usize)> {
+        if buf.len() < 4 {
+            return None;
+        }
+
+        if buf.starts_with(b"ID3") {
+            rewrite_id3v2(buf, tags)
+        } else if buf.starts_with(FLAC_MAGIC) {
+            rewrite_flac(buf, tags)
+        } else if is_mpeg_audio_frame(buf.read_be::<u16>(0)) {
+            // MP3 frame without tag
+            let frames = make_id3_frames(ID3V2_WRITE_VERSION, tags, &[]);
+            Some((make_id3_tag(ID3V2_WRITE_VERSION, &frames), 0))
+        } else {
+            // Unsupported format, leave untouched.
+            Some((vec![], 0))
+        }
+    }
+
+    enum State {
+        Buffering,
+        PassThrough,
+    }
+
+    /// Writes tags to the head of a plain MP3 (ID3v2) or FLAC (Vorbis comment & picture) stream.
+    /// Existing tags are kept; only missing fields are added.
+    pub struct TagWriter {
+        tags: AudioTags,
+        state: State,
+        buf: Vec<u8>,
+    }
+
+    impl TagWriter {
+        pub fn new(tags: AudioTags) -> Self {
+            Self {
+                tags,
+                state: State::Buffering,
+                buf: vec![],
+            }
+        }
+
+        pub fn write(&mut self, data: &[u8], out: &mut Vec<u8>) {
+            match self.state {
+                State::PassThrough => out.extend_from_slice(data),
+                State::Buffering => {
+                    self.buf.extend_from_slice(data);
+                    if let Some((header, replaced)) = rewrite_header(&self.buf, &self.tags) {
+                        out.extend(header);
+                        out.extend_from_slice(&self.buf[replaced..]);
+                        self.buf = vec![];
+                        self.state = State::PassThrough;
+                    }
+                }
+            }
+        }
+
+        /// Flush any data still buffered, untouched.
+        pub fn end(&mut self, out: &mut Vec<u8>) {
+            out.append(&mut self.buf);
+            self.state = State::PassThrough;
+        }
+    }
+}
+
+pub use detail::TagWriter;
+
+#[cfg(test)]
+mod test {
+    use super::{AudioCover, AudioTags, TagWriter};
+    use crate::utils::{
+        audio::metadata::get_audio_header_metadata_size,
+        image::{ImageInfo, ImageType},
+    };
+
+    fn make_test_tags() -> AudioTags {
+        AudioTags {
+            title: "鹦鹉".into(),
+            artists: vec!["Jixun".into(), "Parakeet".into()],
+            album: "Album".into(),
+            cover: Some(AudioCover {
+                data: vec![0xFF, 0xD8, 0xFF, 0xD9],
+                info: ImageInfo {
+                    image_type: ImageType::JPEG,
+                    width: 1,
+                    height: 1,
+                },
+            }),
+        }
+    }
+
+    fn write_all(tags: AudioTags, data: &[u8]) -> Vec<u8> {
+        let mut writer = TagWriter::new(tags);
+        let mut out = vec![];
+        for chunk in data.chunks(3) {
+            writer.write(chunk, &mut out);
+        }
+        writer.end(&mut out);
+        out
+    }
+
+    #[test]
+    fn test_mp3_new_tag() {
+        let audio = [0xFF, 0xFB, 0x90, 0x64, 0x00, 0x00];
+        let out = write_all(make_test_tags(), &audio);
+
+        let tag_size = get_audio_header_metadata_size(&out);
+        assert!(out.starts_with(b"ID3\x03\x00\x00"));
+        assert_eq!(&out[tag_size..], &audio);
+
+        let tag = &out[..tag_size];
+        for id in [b"TIT2", b"TPE1", b"TALB", b"APIC"] {
+            assert!(tag.windows(4).any(|v| v == id));
+        }
+    }
+
+    #[test]
+    fn test_mp3_keep_existing_frames() {
+        let mut audio = vec![];
+        audio.extend_from_slice(b"ID3\x03\x00\x00\x00\x00\x00\x1A");
+        audio.extend_from_slice(b"TIT2\x00\x00\x00\x04\x00\x00\x00Old");
+        audio.extend_from_slice(&[0u8; 12]); // padding
+        audio.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+        let out = write_all(make_test_tags(), &audio);
+
+        let tag_size = get_audio_header_metadata_size(&out);
+        let tag = &out[..tag_size];
+        assert_eq!(&out[tag_size..], &[0xFF, 0xFB, 0x90, 0x64]);
+        assert!(tag.windows(6).any(|v| v == b"\x00\x00\x00Old"));
+        assert_eq!(tag.windows(4).filter(|v| v == b"TIT2").count(), 1);
+        assert!(tag.windows(4).any(|v| v == b"TALB"));
+    }
+
+    #[test]
+    fn test_flac_blocks() {
+        let mut audio = vec![];
+        audio.extend_from_slice(b"fLaC");
+        audio.extend_from_slice(&[0x00, 0x00, 0x00, 0x22]); // STREAMINFO
+        audio.extend_from_slice(&[0x11; 0x22]);
+        audio.extend_from_slice(&[0x81, 0x00, 0x00, 0x04]); // PADDING, last
+        audio.extend_from_slice(&[0x00; 4]);
+        audio.extend_from_slice(&[0xFF, 0xF8, 0x69, 0x08]); // frame
+        let out = write_all(make_test_tags(), &audio);
+
+        let mut offset = 4;
+        let mut block_types = vec![];
+        loop {
+            let header = u32::from_be_bytes(out[offset..offset + 4].try_into().unwrap());
+            block_types.push((header >> 24) & 0x7F);
+            offset += 4 + (header & 0x00FF_FFFF) as usize;
+            if header & 0x8000_0000 != 0 {
+                break;
+            }
+        }
+
+        assert_eq!(block_types, vec![0, 4, 6, 1]);
+        assert_eq!(&out[offset..], &[0xFF, 0xF8, 0x69, 0x08]);
+
+        let comments = &out[4 + 4 + 0x22..];
+        assert!(comments.windows(12).any(|v| v == b"ARTIST=Jixun"));
+        assert!(comments.windows(15).any(|v| v == b"ARTIST=Parakeet"));
+    }
+
+    #[test]
+    fn test_flac_keep_invalid_comment() {
+        // Vorbis comment claiming 2 entries, but holding none.
+        let comment = [0u8, 0, 0, 0, 2, 0, 0, 0];
+        let mut audio = vec![];
+        audio.extend_from_slice(b"fLaC");
+        audio.extend_from_slice(&[0x00, 0x00, 0x00, 0x22]); // STREAMINFO
+        audio.extend_from_slice(&[0x11; 0x22]);
+        audio.extend_from_slice(&[0x84, 0x00, 0x00, 0x08]); // VORBIS_COMMENT, last
+        audio.extend_from_slice(&comment);
+        audio.extend_from_slice(&[0xFF, 0xF8, 0x69, 0x08]); // frame
+        let out = write_all(make_test_tags(), &audio);
+
+        // The picture is still added, before the untouched comment block.
+        let mut comment_block = vec![0x84, 0x00, 0x00, 0x08];
+        comment_block.extend_from_slice(&comment);
+        comment_block.extend_from_slice(&[0xFF, 0xF8, 0x69, 0x08]);
+        assert!(out.ends_with(&comment_block));
+        assert_eq!(out[4 + 4 + 0x22], 0x06);
+        assert!(!out.windows(6).any(|v| v == b"TITLE="));
+    }
+
+    #[test]
+    fn test_unknown_format_untouched() {
+        let audio = b"OggS\x00\x02\x00\x00";
+        assert_eq!(write_all(make_test_tags(), audio), audio);
+
+        // AAC ADTS shares the MPEG frame sync, but has no ID3v2 support.
+        let audio = b"\xFF\xF1\x50\x80\x00\x1F\xFC";
+        assert_eq!(write_all(make_test_tags(), audio), audio);
+    }
+}
@@ -0,0 +1,151 @@
+mod detail {
+    use crate::utils::audio::{detect_audio_type, get_audio_header_metadata_size, AudioType};
+
+    // Enough for any leading tag header (APEv2: 32 bytes).
+    const TAG_HEADER_PROBE_SIZE: usize = 32;
+    // Enough for `detect_audio_type`, including the Matroska DocType.
+    const AUDIO_HEADER_PROBE_SIZE: usize = 64;
+
+    enum State {
+        ReadHeader,
+        SkipTag(usize),
+        Done,
+    }
+
+    /// Detects the audio type of a stream, skipping leading ID3v2/APEv2 tags
+    ///   without buffering them.
+    pub struct AudioTypeSniffer {
+        state: State,
+        buf: Vec<u8>,
+        audio_type: Option<AudioType>,
+    }
+
+    impl Default for AudioTypeSniffer {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl AudioTypeSniffer {
+        pub fn new() -> Self {
+            Self {
+                state: State::ReadHeader,
+                buf: vec![],
+                audio_type: None,
+            }
+        }
+
+        /// `None` until enough data was written, or `end()` was called.
+        pub fn audio_type(&self) -> Option<AudioType> {
+            self.audio_type
+        }
+
+        pub fn write(&mut self, data: &[u8]) {
+            let mut p = data;
+
+            while !p.is_empty() {
+                match self.state {
+                    State::Done => return,
+
+                    State::SkipTag(n) => {
+                        let n_skip = usize::min(n, p.len());
+                        p = &p[n_skip..];
+                        self.state = if n == n_skip {
+                            State::ReadHeader
+                        } else {
+                            State::SkipTag(n - n_skip)
+                        };
+                    }
+
+                    State::ReadHeader => {
+                        let n_read = usize::min(AUDIO_HEADER_PROBE_SIZE - self.buf.len(), p.len());
+                        self.buf.extend_from_slice(&p[..n_read]);
+                        p = &p[n_read..];
+                        self.process_buffer(false);
+                    }
+                }
+            }
+        }
+
+        /// Detect from whatever was buffered, e.g. for very short streams.
+        pub fn end(&mut self) {
+            if let State::ReadHeader = self.state {
+                self.process_buffer(true);
+            }
+            self.finish();
+        }
+
+        fn finish(&mut self) {
+            if self.audio_type.is_none() {
+                self.audio_type = Some(detect_audio_type(&self.buf));
+            }
+            self.buf = vec![];
+            self.state = State::Done;
+        }
+
+        fn process_buffer(&mut self, eof: bool) {
+            loop {
+                if self.buf.len() < TAG_HEADER_PROBE_SIZE && !eof {
+                    return;
+                }
+
+                let tag_size = match self.buf.len() {
+                    0..TAG_HEADER_PROBE_SIZE => 0,
+                    _ => get_audio_header_metadata_size(&self.buf),
+                };
+                if tag_size == 0 {
+                    break;
+                }
+
+                if tag_size <= self.buf.len() {
+                    self.buf.drain(..tag_size);
+                } else {
+                    self.state = State::SkipTag(tag_size - self.buf.len());
+                    self.buf.clear();
+                    return;
+                }
+            }
+
+            if self.buf.len() >= AUDIO_HEADER_PROBE_SIZE || eof {
+                self.finish();
+            }
+        }
+    }
+}
+
+pub use detail::AudioTypeSniffer;
+
+#[cfg(test)]
+mod test {
+    use super::AudioTypeSniffer;
+    use crate::utils::audio::AudioType;
+
+    #[test]
+    fn test_sniff_after_large_id3() {
+        // ID3v2.4 tag with a 1 MiB body (sync-safe size), then FLAC.
+        let mut data = b"ID3\x04\x00\x00\x00\x40\x00\x00".to_vec();
+        data.resize(data.len() + 0x10_0000, 0xAA);
+        data.extend_from_slice(b"fLaC\0\0\0\x22\x12\0\x12\0\0\0\0\0");
+        data.resize(data.len() + 0x100, 0);
+
+        let mut sniffer = AudioTypeSniffer::new();
+        for chunk in data.chunks(0x1001) {
+            sniffer.write(chunk);
+        }
+        assert_eq!(sniffer.audio_type(), Some(AudioType::FLAC));
+    }
+
+    #[test]
+    fn test_sniff_short_stream() {
+        let mut sniffer = AudioTypeSniffer::new();
+        sniffer.write(b"fLaC\0\0\0\x22\x12\0\x12\0\0\0\0\0");
+        assert_eq!(sniffer.audio_type(), None);
+        sniffer.end();
+        assert_eq!(sniffer.audio_type(), Some(AudioType::FLAC));
+
+        let mut sniffer = AudioTypeSniffer::new();
+        sniffer.write(b"ID3\x03\x00\x00\x00\x00\x00\x02ab\xFF\xFB");
+        sniffer.end();
+        assert_eq!(sniffer.audio_type(), Some(AudioType::UnknownType));
+    }
+}
@@ -0,0 +1,259 @@
+// References:
+// - PNG:
+//   https://www.w3.org/TR/png/#5PNG-file-signature
+// - JPEG:
+//   https://www.w3.org/Graphics/JPEG/itu-t81.pdf (Annex B)
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageType {
+    UnknownType,
+    JPEG,
+    PNG,
+}
+
+impl ImageType {
+    pub fn mime_type(&self) -> &'static str {
+        match *self {
+            ImageType::JPEG => "image/jpeg",
+            ImageType::PNG => "image/png",
+            ImageType::UnknownType => "application/octet-stream",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub image_type: ImageType,
+    /// `0` if it could not be determined.
+    pub width: u32,
+    /// `0` if it could not be determined.
+    pub height: u32,
+}
+
+mod detail {
+    use super::{ImageInfo, ImageType};
+    use crate::utils::array_ext::ByteSliceExt;
+
+    const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+    // signature + u32(len) + "IHDR" + u32(width) + u32(height)
+    const PNG_MIN_HEADER_SIZE: usize = 24;
+
+    const JPEG_SOI: &[u8; 2] = &[0xFF, 0xD8];
+    // 0xFF marker u16(len) u8(precision) u16(height) u16(width)
+    const JPEG_SOF_HEADER_SIZE: usize = 9;
+
+    enum State {
+        DetectType,
+        ReadPngHeader,
+        ReadJpegMarker,
+        Skip(usize),
+        Done,
+    }
+
+    /// Detects image type and dimensions from a stream,
+    ///   buffering only the few bytes of the headers it needs.
+    pub struct ImageSniffer {
+        state: State,
+        buf: Vec<u8>,
+        info: ImageInfo,
+    }
+
+    impl Default for ImageSniffer {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl ImageSniffer {
+        pub fn new() -> Self {
+            Self {
+                state: State::DetectType,
+                buf: vec![],
+                info: ImageInfo {
+                    image_type: ImageType::UnknownType,
+                    width: 0,
+                    height: 0,
+                },
+            }
+        }
+
+        pub fn info(&self) -> ImageInfo {
+            self.info
+        }
+
+        pub fn write(&mut self, data: &[u8]) {
+            let mut p = data;
+
+            while !p.is_empty() {
+                match self.state {
+                    State::Done => return,
+
+                    State::Skip(n) => {
+                        let n_skip = usize::min(n, p.len());
+                        p = &p[n_skip..];
+                        self.state = if n == n_skip {
+                            State::ReadJpegMarker
+                        } else {
+                            State::Skip(n - n_skip)
+                        };
+                    }
+
+                    _ => {
+                        self.buf.push(p[0]);
+                        p = &p[1..];
+                        self.process_buffer();
+                    }
+                }
+            }
+        }
+
+        fn process_buffer(&mut self) {
+            match self.state {
+                State::DetectType => self.detect_type(),
+                State::ReadPngHeader => self.read_png_header(),
+                State::ReadJpegMarker => self.read_jpeg_marker(),
+                _ => {}
+            }
+        }
+
+        fn detect_type(&mut self) {
+            if PNG_SIGNATURE.starts_with(&self.buf) {
+                if self.buf.len() == PNG_SIGNATURE.len() {
+                    self.info.image_type = ImageType::PNG;
+                    self.state = State::ReadPngHeader;
+                }
+            } else if JPEG_SOI.starts_with(&self.buf) {
+                if self.buf.len() == JPEG_SOI.len() {
+                    self.info.image_type = ImageType::JPEG;
+                    self.buf.clear();
+                    self.state = State::ReadJpegMarker;
+                }
+            } else {
+                self.state = State::Done;
+            }
+        }
+
+        fn read_png_header(&mut self) {
+            if self.buf.len() == PNG_MIN_HEADER_SIZE {
+                if &self.buf[12..16] == b"IHDR" {
+                    self.info.width = self.buf.read_be::<u32>(16);
+                    self.info.height = self.buf.read_be::<u32>(20);
+                }
+                self.state = State::Done;
+            }
+        }
+
+        fn read_jpeg_marker(&mut self) {
+            let buf = &self.buf;
+            if buf[0] != 0xFF {
+                self.state = State::Done;
+                return;
+            }
+
+            if buf.len() < 2 {
+                return;
+            }
+
+            let marker = buf[1];
+            match marker {
+                // Fill bytes
+                0xFF => {
+                    self.buf.pop();
+                }
+
+                // Standalone markers: TEM, RSTn, SOI
+                0x01 | 0xD0..=0xD8 => {
+                    self.buf.clear();
+                }
+
+                // EOI, SOS: no frame header before image data
+                0xD9 | 0xDA => {
+                    self.state = State::Done;
+                }
+
+                // SOFn, except DHT, JPG and DAC
+                0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                    if buf.len() == JPEG_SOF_HEADER_SIZE {
+                        self.info.height = buf.read_be::<u16>(5) as u32;
+                        self.info.width = buf.read_be::<u16>(7) as u32;
+                        self.state = State::Done;
+                    }
+                }
+
+                _ => {
+                    if buf.len() == 4 {
+                        let segment_len = buf.read_be::<u16>(2) as usize;
+                        self.buf.clear();
+                        if segment_len < 2 {
+                            self.state = State::Done;
+                        } else if segment_len > 2 {
+                            self.state = State::Skip(segment_len - 2);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn sniff_image<T: AsRef<[u8]>>(buf: T) -> ImageInfo {
+        let mut sniffer = ImageSniffer::new();
+        sniffer.write(buf.as_ref());
+        sniffer.info()
+    }
+}
+
+pub use detail::sniff_image;
+pub use detail::ImageSniffer;
+
+#[cfg(test)]
+mod test {
+    use super::{sniff_image, ImageInfo, ImageSniffer, ImageType};
+
+    #[test]
+    fn test_sniff_png() {
+        let png: &[u8] = &[
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, // signature
+            0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52, // IHDR
+            0x00, 0x00, 0x01, 0xF4, 0x00, 0x00, 0x01, 0x2C, // 500x300
+            0x08, 0x06, 0x00, 0x00, 0x00, //
+        ];
+
+        let expected = ImageInfo {
+            image_type: ImageType::PNG,
+            width: 500,
+            height: 300,
+        };
+        assert_eq!(sniff_image(png), expected);
+    }
+
+    #[test]
+    fn test_sniff_jpeg_streamed() {
+        let mut jpeg = vec![0xFF, 0xD8];
+        // APP1 segment, larger than a single write
+        jpeg.extend_from_slice(&[0xFF, 0xE1, 0x10, 0x02]);
+        jpeg.extend_from_slice(&[0xAA; 0x1000]);
+        // SOF0: 8-bit, 1080x1920
+        jpeg.extend_from_slice(&[0xFF, 0xFF, 0xC0, 0x00, 0x11, 0x08, 0x07, 0x80, 0x04, 0x38]);
+        jpeg.extend_from_slice(&[0x00; 0x0F]);
+
+        let mut sniffer = ImageSniffer::new();
+        for chunk in jpeg.chunks(7) {
+            sniffer.write(chunk);
+        }
+
+        let expected = ImageInfo {
+            image_type: ImageType::JPEG,
+            width: 1080,
+            height: 1920,
+        };
+        assert_eq!(sniffer.info(), expected);
+        assert_eq!(sniff_image(&jpeg), expected);
+    }
+
+    #[test]
+    fn test_sniff_unknown() {
+        let info = sniff_image(b"GIF89a");
+        assert_eq!(info.image_type, ImageType::UnknownType);
+    }
+}
@@ -1,4 +1,5 @@
 pub mod audio;
+pub mod image;
 
 pub(crate) mod aes_ecb;
 pub(crate) mod array_ext;
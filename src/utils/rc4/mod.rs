@@ -5,5 +5,6 @@ mod tencent_qmc_v2;
 
 pub use base::{RC4Derive, RC4State};
 pub use netease::RC4Netease;
+#[allow(unused_imports)]
 pub use standard::RC4Standard;
 pub use tencent_qmc_v2::RC4TencentQmcV2;
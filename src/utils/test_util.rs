@@ -18,6 +18,21 @@ pub mod test {
     #[allow(unused)]
     pub const TEST_SIZE_8MB: usize = TEST_SIZE_1MB * 8;
 
+    // ekey of `generate_test_data(512, "qmcv2 rc4 cipher key")` with its first 8 bytes set to `4`.
+    #[allow(unused)]
+    pub const TEST_QMC_V2_RC4_EKEY: &str = concat!(
+        "NDQ0NDQ0NDQeUefW/SNkzbCL/cLZx5vkzk/fVdAN3tJaTQt6ES1bX3qHHwYFJKiFcQBYf1bU1Ywf",
+        "jzpJxGa1tmLwuLL648K5zYEzPDQzigPQyi55pfR9MZxTC5LoCwWj5LK6kaLoWs3yzQ0rDkMEpfbp",
+        "s1hl+0Xo341OI9uTrJ8MFK3OiihEXItE74RCDB5fLpuGB1M+WMdETNR6F2Yd+QdKZcIrXiJXOmCu",
+        "5zvxFPxSq6ofpg23K4NM26Z/nGgeWIHQqVqCXaXmKiKTSYSpdtbqNsITUa0PqEbLF0h0ZjQba2+N",
+        "3udJPQJRUMJKbhpIvlJWoHDQzy5D4fmgf49aPYwx5daPRCnA9t7MfbExXguDHKtRHGyUbNJD5OZ3",
+        "CHOe5sa+7AxQ+64qhyzaXTi4wM4mNn/EZxIwFzTIGGqzv11qOZFaWhgNBUPorMiMZe0BpF4OdCZG",
+        "AXh/MBFp79Ruoiwp/nhp9AodEyEC8ni2rjaJGe33wjNpjzL5HUq4qiax1t6o+KcUdmZvQdx+wfo5",
+        "gSkavob1Bwm5Nyq93YnPnXEttR2pp+c04fmpdIPu0OQgaX0WPsTYRO7i4xAab2s77UiVP4IXxsY8",
+        "aHzDG3IRMalr7fHFLLVSX9bqlk8kigao5gho2/oZD6eT1Uct59WesYQ/q3yST0PCSMCCefwONYgI",
+        "8IcRDCzOgguq/P3uZpO9"
+    );
+
     pub fn generate_test_data(len: usize, name: &str) -> Vec<u8> {
         let mut result = vec![0u8; len];
 
@@ -33,7 +48,7 @@ pub mod test {
         let mut context = Context::new(&SHA256);
         context.update(data);
         let result = context.finish();
-        return data_encoding::HEXLOWER.encode(result.as_ref());
+        data_encoding::HEXLOWER.encode(result.as_ref())
     }
 
     pub fn decrypt_test_content<T: AsRef<[u8]>>(decryptor: &mut impl Decryptor, data: T) -> String {